@@ -0,0 +1,416 @@
+//! タスク表示のボタンの実装
+//!
+//! `/show`のタスクごとに「開始」「完了」「編集」「削除」のボタンを付ける.
+//! ボタンのIDに`task:<操作>:<タスクID>`を入れておき, イベントハンドラで受け取るので,
+//! コマンドの待ち受けが時間切れになった後でも押せる.
+//!
+//! 「編集」はタスク名・概要・〆切を入力するモーダルを開き, 送信されたらそのまま反映する.
+//! 「削除」は確認のボタンをもう一度押してから消す.
+//! ステータスの変更と削除は`/status`・`/remove`と同じく`/undo`で元に戻せる.
+
+use crate::board;
+use crate::db::{connect_to_db, locate_task, purge_task, task_summary};
+use crate::imp;
+use crate::support::short_id;
+use crate::undo;
+use crate::workflow;
+use chrono::NaiveDate;
+use poise::serenity_prelude::{
+    self as serenity, ActionRowComponent, ButtonStyle, ComponentInteraction, CreateActionRow,
+    CreateButton, CreateInputText, CreateInteractionResponse, CreateInteractionResponseMessage,
+    CreateModal, InputTextStyle, ModalInteraction,
+};
+use tokio_postgres::Client;
+
+/// ボタンとモーダルのIDの接頭辞
+pub const PREFIX: &str = "task:";
+
+/// モーダルに入れられるタスク名の文字数（Embedのタイトルの上限）
+const NAME_CHARS: u16 = 256;
+
+/// モーダルに入れられる概要の文字数（Discordの上限）
+const DESCRIPTION_CHARS: u16 = 4000;
+
+/// タスク1件分のボタン
+///
+/// 先頭に押せないボタンで`#1a2b3c4d`を出して, どのタスクのボタンか分かるようにする.
+/// 既に進行中・完了扱いのタスクはそれぞれ「開始」「完了」を押せなくする.
+///
+/// # 引数
+///
+/// * `task_id` - タスクのID
+/// * `started` - 「開始」で移る状態になっているか
+/// * `done` - 完了扱いの状態か
+pub fn buttons(task_id: &uuid::Uuid, started: bool, done: bool) -> CreateActionRow {
+    CreateActionRow::Buttons(vec![
+        CreateButton::new(format!("{}label:{}", PREFIX, task_id))
+            .label(format!("#{}", short_id(task_id)))
+            .style(ButtonStyle::Secondary)
+            .disabled(true),
+        CreateButton::new(format!("{}start:{}", PREFIX, task_id))
+            .label("開始")
+            .style(ButtonStyle::Primary)
+            .disabled(started || done),
+        CreateButton::new(format!("{}done:{}", PREFIX, task_id))
+            .label("完了")
+            .style(ButtonStyle::Success)
+            .disabled(done),
+        CreateButton::new(format!("{}edit:{}", PREFIX, task_id))
+            .label("編集")
+            .style(ButtonStyle::Secondary),
+        CreateButton::new(format!("{}remove:{}", PREFIX, task_id))
+            .label("削除")
+            .style(ButtonStyle::Danger),
+    ])
+}
+
+/// 「開始」で移る状態
+///
+/// 最初の状態以外で最初の未完了の状態. 既定のワークフローなら「進行中」.
+pub fn start_state(states: &[workflow::State]) -> Option<i16> {
+    states
+        .iter()
+        .find(|s| !s.done && s.code != 1)
+        .map(|s| s.code)
+}
+
+/// `task:<操作>:<タスクID>`を分解する
+fn parse_id(custom_id: &str) -> Option<(&str, uuid::Uuid)> {
+    let (action, task_id) = custom_id.strip_prefix(PREFIX)?.split_once(':')?;
+    Some((action, uuid::Uuid::parse_str(task_id).ok()?))
+}
+
+/// 自分だけに見えるメッセージで応答する
+async fn respond(ctx: &serenity::Context, mi: &ComponentInteraction, content: impl Into<String>) {
+    let _ = mi
+        .create_response(
+            ctx,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .ephemeral(true)
+                    .content(content),
+            ),
+        )
+        .await;
+}
+
+/// タスクのボタンが押されたときの処理
+///
+/// # 引数
+///
+/// * `ctx` - イベントを受け取ったときのコンテキスト
+/// * `mi` - ボタンの操作
+pub async fn on_button(ctx: &serenity::Context, mi: &ComponentInteraction) {
+    let Some((action, task_id)) = parse_id(&mi.data.custom_id) else {
+        return;
+    };
+    let Ok(client) = connect_to_db().await else {
+        respond(ctx, mi, "データベースに接続できませんでした").await;
+        return;
+    };
+    // 表示した後に移動・削除されていることもあるので, 今あるチャンネルを探し直す
+    let Some(channel_id) = locate_task(&client, &task_id).await else {
+        respond(
+            ctx,
+            mi,
+            "タスクが見つかりません（削除された可能性があります）",
+        )
+        .await;
+        return;
+    };
+    let user_id = mi.user.id.to_string();
+
+    match action {
+        // ---------- ステータスの変更 ----------
+        "start" | "done" => {
+            let states = workflow::states_of(&ctx.http, &client, &channel_id).await;
+            let to = if action == "start" {
+                start_state(&states)
+            } else {
+                Some(0)
+            };
+            let Some(to) = to else {
+                respond(ctx, mi, "このワークフローには開始の状態がありません").await;
+                return;
+            };
+            match change_status(ctx, mi, &client, &channel_id, &task_id, &states, to).await {
+                Ok(msg) | Err(msg) => respond(ctx, mi, msg).await,
+            }
+        }
+        // ---------- 編集 ----------
+        "edit" => {
+            let Some(modal) = edit_modal(&client, &channel_id, &task_id).await else {
+                respond(ctx, mi, "タスクが見つかりません").await;
+                return;
+            };
+            let _ = mi
+                .create_response(ctx, CreateInteractionResponse::Modal(modal))
+                .await;
+        }
+        // ---------- 削除の確認 ----------
+        "remove" => {
+            let (task_name, _) = task_summary(&client, &channel_id, &task_id)
+                .await
+                .unwrap_or_default();
+            let confirm = CreateActionRow::Buttons(vec![CreateButton::new(format!(
+                "{}confirm:{}",
+                PREFIX, task_id
+            ))
+            .label("削除する")
+            .style(ButtonStyle::Danger)]);
+            let _ = mi
+                .create_response(
+                    ctx,
+                    CreateInteractionResponse::Message(
+                        CreateInteractionResponseMessage::new()
+                            .ephemeral(true)
+                            .content(format!("タスク「{}」を本当に削除しますか？", task_name))
+                            .components(vec![confirm]),
+                    ),
+                )
+                .await;
+        }
+        // ---------- 削除 ----------
+        "confirm" => {
+            let content = match remove(ctx, &client, &channel_id, &task_id, &user_id).await {
+                Ok(msg) | Err(msg) => msg,
+            };
+            let _ = mi
+                .create_response(
+                    ctx,
+                    CreateInteractionResponse::UpdateMessage(
+                        CreateInteractionResponseMessage::new()
+                            .content(content)
+                            .components(vec![]),
+                    ),
+                )
+                .await;
+        }
+        _ => {}
+    }
+}
+
+/// ボタンからステータスを変える
+///
+/// 遷移ルールで許されていない, またはコメントが必要な場合は変えずに断る.
+async fn change_status(
+    ctx: &serenity::Context,
+    mi: &ComponentInteraction,
+    client: &Client,
+    channel_id: &str,
+    task_id: &uuid::Uuid,
+    states: &[workflow::State],
+    to: i16,
+) -> Result<String, String> {
+    let Some((task_name, from)) = task_summary(client, channel_id, task_id).await else {
+        return Err("タスクが見つかりません".to_string());
+    };
+    if from == to {
+        return Err(format!(
+            "タスク「{}」は既に「{}」です",
+            task_name,
+            workflow::name_of(states, to)
+        ));
+    }
+
+    // ---------- 遷移ルールの確認 ----------
+    let guild_id = mi.guild_id.map(|id| id.to_string());
+    let rules = workflow::transitions(client, channel_id, guild_id.as_deref(), from).await;
+    match workflow::check_transition(states, &rules, from, to) {
+        Err(msg) => return Err(msg),
+        Ok(true) => {
+            return Err(format!(
+                "「{}」から「{}」にするにはコメントが必要です. /status の comment に理由を書いて実行してください",
+                workflow::name_of(states, from),
+                workflow::name_of(states, to)
+            ))
+        }
+        Ok(false) => {}
+    }
+
+    // ---------- 反映 ----------
+    let user_id = mi.user.id.to_string();
+    undo::remember(client, &user_id, channel_id, task_id, "status").await;
+    let query = format!("update \"{}\" set status=$1 where id=$2;", channel_id);
+    match client.execute(&query, &[&to, task_id]).await {
+        Ok(1) => {}
+        _ => {
+            undo::forget(client, &user_id).await;
+            return Err("ステータスを変更できませんでした".to_string());
+        }
+    }
+    imp::after_status_change(&ctx.http, client, channel_id, task_id, to).await;
+    Ok(format!(
+        "タスク「{}」のステータスを「{}」にしました（/undo で元に戻せます）",
+        task_name,
+        workflow::name_of(states, to)
+    ))
+}
+
+/// ボタンからタスクを削除する
+///
+/// テーブルが空になったらテーブルごと消すのは`/remove`と同じ.
+async fn remove(
+    ctx: &serenity::Context,
+    client: &Client,
+    channel_id: &str,
+    task_id: &uuid::Uuid,
+    user_id: &str,
+) -> Result<String, String> {
+    let (task_name, _) = task_summary(client, channel_id, task_id)
+        .await
+        .unwrap_or_default();
+
+    undo::remember(client, user_id, channel_id, task_id, "remove").await;
+    let query = format!("delete from \"{}\" where id=$1;", channel_id);
+    match client.execute(&query, &[task_id]).await {
+        Ok(1) => {}
+        _ => {
+            undo::forget(client, user_id).await;
+            return Err("タスクを削除できませんでした".to_string());
+        }
+    }
+    purge_task(client, task_id).await;
+
+    // ---------- 0行だったらテーブルごと削除 ----------
+    let count_query = format!("select count(*) from \"{}\";", channel_id);
+    if let Ok(rows) = client.query(&count_query, &[]).await {
+        if rows[0].get::<&str, i64>("count") == 0 {
+            let _ = client
+                .execute(&format!("drop table \"{}\";", channel_id), &[])
+                .await;
+        }
+    }
+    board::refresh(&ctx.http, client, channel_id).await;
+
+    Ok(format!(
+        "タスク「{}」を削除しました（/undo で元に戻せます）",
+        task_name
+    ))
+}
+
+/// 編集用のモーダルを作る
+///
+/// 今のタスク名・概要・〆切を入れておく.
+async fn edit_modal(
+    client: &Client,
+    channel_id: &str,
+    task_id: &uuid::Uuid,
+) -> Option<CreateModal> {
+    let query = format!(
+        "select task_name, description, deadline from \"{}\" where id=$1;",
+        channel_id
+    );
+    let rows = client.query(&query, &[task_id]).await.ok()?;
+    let row = rows.first()?;
+    let task_name: String = row.get("task_name");
+    let description: Option<String> = row.get("description");
+    let deadline: Option<NaiveDate> = row.get("deadline");
+
+    let name = CreateInputText::new(InputTextStyle::Short, "タスク名", "task_name")
+        .value(task_name)
+        .max_length(NAME_CHARS)
+        .required(true);
+    let mut description_input =
+        CreateInputText::new(InputTextStyle::Paragraph, "概要", "description")
+            .max_length(DESCRIPTION_CHARS)
+            .required(false);
+    if let Some(d) = description {
+        description_input = description_input.value(d);
+    }
+    let mut deadline_input = CreateInputText::new(InputTextStyle::Short, "〆切", "deadline")
+        .placeholder("YYYY-MM-DD（空欄で〆切なし）")
+        .required(false);
+    if let Some(d) = deadline {
+        deadline_input = deadline_input.value(d.format("%Y-%m-%d").to_string());
+    }
+
+    Some(
+        CreateModal::new(format!("{}edit:{}", PREFIX, task_id), "タスクの編集").components(vec![
+            CreateActionRow::InputText(name),
+            CreateActionRow::InputText(description_input),
+            CreateActionRow::InputText(deadline_input),
+        ]),
+    )
+}
+
+/// モーダルの入力欄の値
+fn input_value(modal: &ModalInteraction, custom_id: &str) -> Option<String> {
+    modal
+        .data
+        .components
+        .iter()
+        .flat_map(|row| row.components.iter())
+        .find_map(|component| match component {
+            ActionRowComponent::InputText(input) if input.custom_id == custom_id => {
+                input.value.clone()
+            }
+            _ => None,
+        })
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// 編集のモーダルが送信されたときの処理
+///
+/// # 引数
+///
+/// * `ctx` - イベントを受け取ったときのコンテキスト
+/// * `modal` - モーダルの送信内容
+pub async fn on_modal(ctx: &serenity::Context, modal: &ModalInteraction) {
+    let Some(("edit", task_id)) = parse_id(&modal.data.custom_id) else {
+        return;
+    };
+    let content = match edit(ctx, modal, &task_id).await {
+        Ok(msg) | Err(msg) => msg,
+    };
+    let _ = modal
+        .create_response(
+            ctx,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .ephemeral(true)
+                    .content(content),
+            ),
+        )
+        .await;
+}
+
+/// モーダルの内容でタスクを書き換える
+async fn edit(
+    ctx: &serenity::Context,
+    modal: &ModalInteraction,
+    task_id: &uuid::Uuid,
+) -> Result<String, String> {
+    let Some(task_name) = input_value(modal, "task_name") else {
+        return Err("タスク名を入力してください".to_string());
+    };
+    let description = input_value(modal, "description");
+    let deadline = match input_value(modal, "deadline") {
+        Some(d) => match NaiveDate::parse_from_str(&d, "%Y-%m-%d") {
+            Ok(date) => Some(date),
+            Err(_) => return Err("〆切は YYYY-MM-DD の形式で入力してください".to_string()),
+        },
+        None => None,
+    };
+
+    let client = connect_to_db()
+        .await
+        .map_err(|_| "データベースに接続できませんでした".to_string())?;
+    let Some(channel_id) = locate_task(&client, task_id).await else {
+        return Err("タスクが見つかりません（削除された可能性があります）".to_string());
+    };
+    let query = format!(
+        "update \"{}\" set task_name=$1, description=$2, deadline=$3 where id=$4;",
+        channel_id
+    );
+    match client
+        .execute(&query, &[&task_name, &description, &deadline, task_id])
+        .await
+    {
+        Ok(1) => {}
+        _ => return Err("タスクを編集できませんでした".to_string()),
+    }
+    board::refresh(&ctx.http, &client, &channel_id).await;
+    Ok(format!("タスク「{}」を編集しました", task_name))
+}
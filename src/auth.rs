@@ -6,6 +6,17 @@ use std::env;
 
 pub type Context<'a> = poise::Context<'a, super::Data, serenity::Error>;
 
+/// `.env`に書かれたギルドIDと一致するかどうか
+///
+/// コマンド以外（イベントハンドラなど）でも使う.
+pub fn is_authorized_guild(guild_id: GuildId) -> bool {
+    // .envからギルドIDとってくる
+    let env_guild = env::var("GUILD_ID").expect("missing get token");
+    let env_guild = GuildId::new(env_guild.parse::<u64>().unwrap());
+
+    guild_id == env_guild
+}
+
 /// ギルドIDを比較するメソッド
 ///
 /// # 引数
@@ -17,17 +28,12 @@ pub type Context<'a> = poise::Context<'a, super::Data, serenity::Error>;
 ///
 /// これはBotが他のサーバーに招待されDBの中身（タスク）を見られることを防ぐ目的がある.
 ///
-
 pub async fn auth(ctx: Context<'_>) -> Result<(), serenity::Error> {
     // ctxからguildid取得
     let guild_id = ctx.guild_id().unwrap();
 
-    // .envからギルドIDとってくる
-    let env_guild = env::var("GUILD_ID").expect("missing get token");
-    let env_guild = GuildId::new(env_guild.parse::<u64>().unwrap());
-
     // ギルドが違っていた場合
-    if guild_id != env_guild {
+    if !is_authorized_guild(guild_id) {
         let _ = ctx
             .send(
                 CreateReply::default()
@@ -35,9 +41,7 @@ pub async fn auth(ctx: Context<'_>) -> Result<(), serenity::Error> {
                     .content("⚠ このサーバーでは実行できません"),
             )
             .await;
-        return Err(serenity::Error::Other(
-            "This is an unauthorized guild.".into(),
-        ));
+        return Err(serenity::Error::Other("This is an unauthorized guild."));
     }
 
     Ok(())
@@ -51,10 +55,10 @@ pub async fn auth(ctx: Context<'_>) -> Result<(), serenity::Error> {
 /// パスワードを要求するスラッシュコマンドに利用する
 ///
 /// 入力された引数と照らしあわせる文字列は`.env`ファイルの`PASSWORD=`に記述
-pub async fn passwd(ctx: Context<'_>, password: String) -> Result<(), serenity::Error> {
+pub async fn passwd(_ctx: Context<'_>, password: String) -> Result<(), serenity::Error> {
     let check_pass = env::var("PASSWORD").expect("missing get token");
     if password != check_pass {
-        return Err(serenity::Error::Other("failed password".into()));
+        return Err(serenity::Error::Other("failed password"));
     }
     Ok(())
 }
@@ -0,0 +1,191 @@
+//! カンバンボードの実装
+//!
+//! `/board`でチャンネルにステータスごとの列を並べたメッセージを投稿してピン留めする.
+//! メッセージIDは`kipp.boards`に保存するので, Botを再起動しても同じメッセージを更新し続ける.
+//!
+//! タスクの追加・削除・ステータス変更のたびに`refresh`で書き換える.
+
+use crate::db::connect_to_db;
+use crate::support::{reply, short_id};
+use crate::workflow;
+use poise::serenity_prelude::{
+    self as serenity, ChannelId, CreateEmbed, CreateEmbedFooter, CreateMessage, EditMessage, Http,
+    MessageId, Timestamp,
+};
+use tokio_postgres::Client;
+
+pub type Context<'a> = poise::Context<'a, super::Data, serenity::Error>;
+
+/// 1列に並べるタスクの上限
+const COLUMN_LIMIT: usize = 10;
+
+/// Embedのフィールドの文字数の上限（Discordの上限）
+const FIELD_CHARS: usize = 1024;
+
+/// 1列に並べるタスク名の文字数
+const NAME_CHARS: usize = 30;
+
+/// ボードのEmbedを作る
+///
+/// ワークフローの状態ごとに列（フィールド）を並べる. 列の中は〆切の近い順.
+async fn render(client: &Client, channel_id: &str, states: &[workflow::State]) -> CreateEmbed {
+    let query = format!(
+        "select id, task_name, status from \"{}\"
+        order by deadline nulls last, created_at desc;",
+        channel_id
+    );
+    // タスクが無くてテーブルごと消えている場合もある
+    let rows = client.query(&query, &[]).await.unwrap_or_default();
+
+    let mut embed = CreateEmbed::default()
+        .title("タスクボード")
+        .footer(CreateEmbedFooter::new("最終更新"))
+        .timestamp(Timestamp::now());
+    for state in states {
+        let tasks: Vec<String> = rows
+            .iter()
+            .filter(|row| row.get::<&str, i16>("status") == state.code)
+            .map(|row| {
+                let task_id: uuid::Uuid = row.get("id");
+                let task_name: String = row.get("task_name");
+                let mut name: String = task_name.chars().take(NAME_CHARS).collect();
+                if NAME_CHARS < task_name.chars().count() {
+                    name.push('\u{2026}');
+                }
+                format!("`#{}` {}", short_id(&task_id), name)
+            })
+            .collect();
+
+        let mut lines: Vec<String> = Vec::new();
+        let mut chars: usize = 0;
+        for task in tasks.iter().take(COLUMN_LIMIT) {
+            // 「ほかN件」の分を残しておく
+            if FIELD_CHARS - 16 < chars + task.chars().count() + 1 {
+                break;
+            }
+            chars += task.chars().count() + 1;
+            lines.push(task.clone());
+        }
+        if lines.len() < tasks.len() {
+            lines.push(format!("ほか{}件", tasks.len() - lines.len()));
+        }
+        if lines.is_empty() {
+            lines.push("-".to_string());
+        }
+        embed = embed.field(
+            format!("{} ({})", state.name, tasks.len()),
+            lines.join("\n"),
+            true,
+        );
+    }
+    embed
+}
+
+/// チャンネルにボードがあれば書き換える
+///
+/// ボードのメッセージが消されていたら登録も消す.
+pub async fn refresh(http: &Http, client: &Client, channel_id: &str) {
+    let Ok(rows) = client
+        .query(
+            "select message_id from kipp.boards where channel_id=$1;",
+            &[&channel_id],
+        )
+        .await
+    else {
+        return;
+    };
+    let Some(row) = rows.first() else {
+        return;
+    };
+    let (Ok(ch), Ok(msg)) = (
+        channel_id.parse::<u64>(),
+        row.get::<&str, String>("message_id").parse::<u64>(),
+    ) else {
+        return;
+    };
+
+    let states = workflow::states_of(http, client, channel_id).await;
+    let embed = render(client, channel_id, &states).await;
+    let res = ChannelId::new(ch)
+        .edit_message(http, MessageId::new(msg), EditMessage::new().embed(embed))
+        .await;
+    if let Err(serenity::Error::Http(e)) = res {
+        if e.status_code().map(|s| s.as_u16()) == Some(404) {
+            let _ = client
+                .execute(
+                    "delete from kipp.boards where channel_id=$1;",
+                    &[&channel_id],
+                )
+                .await;
+        }
+    }
+}
+
+/// チャンネルにボードを投稿してピン留めする
+///
+/// 既にボードがある場合は古いメッセージを消して投稿し直す.
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+pub async fn post(ctx: Context<'_>) -> Result<(), serenity::Error> {
+    let channel_id = ctx.channel_id();
+    let channel_str = channel_id.to_string();
+    let client = connect_to_db().await?;
+    let http = ctx.http();
+
+    // ---------- 古いボードを消す ----------
+    if let Ok(rows) = client
+        .query(
+            "select message_id from kipp.boards where channel_id=$1;",
+            &[&channel_str],
+        )
+        .await
+    {
+        if let Some(old) = rows
+            .first()
+            .and_then(|row| row.get::<&str, String>("message_id").parse::<u64>().ok())
+        {
+            let _ = channel_id.delete_message(http, MessageId::new(old)).await;
+        }
+    }
+
+    // ---------- 投稿してピン留め ----------
+    let guild_id = ctx.guild_id().map(|id| id.to_string());
+    let states = workflow::states(&client, &channel_str, guild_id.as_deref()).await;
+    let embed = render(&client, &channel_str, &states).await;
+    let msg = match channel_id
+        .send_message(http, CreateMessage::new().embed(embed))
+        .await
+    {
+        Ok(msg) => msg,
+        Err(e) => {
+            reply(ctx, "ボードを投稿できませんでした").await;
+            return Err(e);
+        }
+    };
+    let pinned = msg.pin(http).await.is_ok();
+
+    let res = client
+        .execute(
+            "insert into kipp.boards (channel_id, message_id) values ($1, $2)
+            on conflict (channel_id) do update set message_id = excluded.message_id;",
+            &[&channel_str, &msg.id.to_string()],
+        )
+        .await;
+    if res.is_err() {
+        reply(ctx, "ボードを登録できませんでした").await;
+        return Err(serenity::Error::Other("ボードの登録に失敗しました"));
+    }
+
+    if pinned {
+        reply(ctx, "ボードを投稿しました").await;
+    } else {
+        reply(
+            ctx,
+            "ボードを投稿しました（ピン留めできませんでした. 権限を確認してください）",
+        )
+        .await;
+    }
+    Ok(())
+}
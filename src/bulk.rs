@@ -0,0 +1,306 @@
+//! タスクの一括操作の実装
+//!
+//! タスクIDの列挙か絞り込み条件で対象を決め, ステータス変更・担当者変更・削除を
+//! まとめて行う. 実行前に対象の一覧を見せて`/remove`と同じボタンで確認を取る.
+
+use crate::board;
+use crate::db::{connect_to_db, purge_task, task_summary};
+use crate::disp::field_lines;
+use crate::imp::{self, confirm};
+use crate::support::{reply, short_id};
+use crate::workflow;
+use poise::serenity_prelude as serenity;
+use tokio_postgres::types::ToSql;
+use tokio_postgres::Client;
+
+pub type Context<'a> = poise::Context<'a, super::Data, serenity::Error>;
+
+/// 確認メッセージに並べるタスクの上限
+const SUMMARY_LIMIT: usize = 20;
+
+/// 対象のタスクの選び方
+///
+/// 指定したものはすべて満たす必要がある（and条件）.
+#[derive(Default)]
+pub struct Filter {
+    /// カンマか空白区切りのタスクID
+    pub task_ids: Option<String>,
+    /// 担当者
+    pub member: Option<serenity::User>,
+    /// `true`なら〆切を過ぎた未完了のタスク
+    pub overdue: Option<bool>,
+    /// タグ
+    pub tag: Option<String>,
+}
+
+/// 条件に合うこのチャンネルのタスクを(ID, タスク名)で取得
+///
+/// 条件が1つも無いときや, タスクIDに解釈できないものがあるときは`None`.
+async fn select(
+    client: &Client,
+    channel_id: &str,
+    filter: Filter,
+) -> Option<Vec<(uuid::Uuid, String)>> {
+    // ---------- 絞り込み条件 ----------
+    let ids: Option<Vec<uuid::Uuid>> = match &filter.task_ids {
+        Some(ids) => Some(
+            ids.split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|id| !id.is_empty())
+                .map(uuid::Uuid::parse_str)
+                .collect::<Result<_, _>>()
+                .ok()?,
+        ),
+        None => None,
+    };
+    let usr_id: Option<String> = filter.member.map(|usr| usr.id.to_string());
+
+    let mut conds: Vec<String> = Vec::new();
+    let mut params: Vec<&(dyn ToSql + Sync)> = Vec::new();
+    if let Some(ids) = &ids {
+        params.push(ids);
+        conds.push(format!("id = any(${})", params.len()));
+    }
+    if let Some(usr_id) = &usr_id {
+        params.push(usr_id);
+        conds.push(format!("member=${}", params.len()));
+    }
+    if filter.overdue == Some(true) {
+        conds.push("deadline < current_date and status > 0".to_string());
+    }
+    if let Some(tag) = &filter.tag {
+        params.push(tag);
+        conds.push(format!(
+            "id in (select task_id from kipp.task_tags where tag=${})",
+            params.len()
+        ));
+    }
+    // 条件なしで全部のタスクを書き換えるのは事故の元なので受け付けない
+    if conds.is_empty() {
+        return None;
+    }
+
+    let query = format!(
+        "select id, task_name from \"{}\" where {} order by deadline nulls last;",
+        channel_id,
+        conds.join(" and ")
+    );
+    let rows = client.query(&query, &params).await.unwrap_or_default();
+    Some(
+        rows.iter()
+            .map(|row| (row.get("id"), row.get("task_name")))
+            .collect(),
+    )
+}
+
+/// 対象のタスクを`#1234abcd タスク名`の形で並べる
+///
+/// メッセージの上限（2000文字）を超えないように, 件数と文字数で切って「ほかN件」にまとめる.
+fn summary(tasks: &[(uuid::Uuid, String)]) -> String {
+    let lines: Vec<String> = tasks
+        .iter()
+        .map(|(id, name)| format!("`#{}` {}", short_id(id), name))
+        .collect();
+    field_lines(&lines, SUMMARY_LIMIT)
+}
+
+/// 対象を選んで確認を取る
+///
+/// 実行してよければ対象のタスクを返す.
+async fn prepare(
+    ctx: Context<'_>,
+    client: &Client,
+    filter: Filter,
+    action: &str,
+) -> Result<Vec<(uuid::Uuid, String)>, serenity::Error> {
+    let channel_id = ctx.channel_id().to_string();
+    let Some(tasks) = select(client, &channel_id, filter).await else {
+        reply(
+            ctx,
+            "タスクIDか絞り込み条件を正しく指定してください（タスクIDはカンマ区切り）",
+        )
+        .await;
+        return Err(serenity::Error::Other("対象の指定が不正です"));
+    };
+    if tasks.is_empty() {
+        reply(ctx, "該当するタスクはありません").await;
+        return Err(serenity::Error::Other("タスクがありません"));
+    }
+
+    let question = format!(
+        "以下の{}件を{}. よろしいですか？\n{}",
+        tasks.len(),
+        action,
+        summary(&tasks)
+    );
+    match confirm(ctx, &question).await {
+        Some(true) => Ok(tasks),
+        Some(false) => {
+            reply(ctx, "中止しました").await;
+            Err(serenity::Error::Other("中止しました"))
+        }
+        None => Err(serenity::Error::Other("タイムアウトしました")),
+    }
+}
+
+/// 条件に合うタスクのステータスをまとめて変更
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `filter` - 対象のタスク
+/// * `status` - 変更後のステータス名（チャンネルのワークフローの状態）
+pub async fn status(
+    ctx: Context<'_>,
+    filter: Filter,
+    status: String,
+) -> Result<(), serenity::Error> {
+    let channel_id = ctx.channel_id().to_string();
+    let client = connect_to_db().await?;
+
+    // ---------- 状態名から番号へ ----------
+    let guild_id = ctx.guild_id().map(|id| id.to_string());
+    let states = workflow::states(&client, &channel_id, guild_id.as_deref()).await;
+    let Some(state) = states.iter().find(|s| s.name == status.trim()) else {
+        reply(
+            ctx,
+            "そのステータスはこのチャンネルのワークフローにありません",
+        )
+        .await;
+        return Err(serenity::Error::Other("ステータスが見つかりません"));
+    };
+    let (status, name) = (state.code, state.name.clone());
+
+    let action = format!("「{}」にします", name);
+    let tasks = prepare(ctx, &client, filter, &action).await?;
+
+    let query = format!(
+        "update \"{}\" set status=$1 where id=$2 and status != $1;",
+        channel_id
+    );
+    let mut changed: Vec<(uuid::Uuid, String)> = Vec::new();
+    let mut skipped: usize = 0;
+    for (id, task_name) in tasks {
+        // ---------- 遷移ルールの確認 ----------
+        // 許されていない変更やコメントが必要な変更は飛ばす
+        if let Some((_, from)) = task_summary(&client, &channel_id, &id).await {
+            let rules =
+                workflow::transitions(&client, &channel_id, guild_id.as_deref(), from).await;
+            if from != status
+                && !matches!(
+                    workflow::check_transition(&states, &rules, from, status),
+                    Ok(false)
+                )
+            {
+                skipped += 1;
+                continue;
+            }
+        }
+        if let Ok(1) = client.execute(&query, &[&status, &id]).await {
+            imp::after_status_change(ctx.http(), &client, &channel_id, &id, status).await;
+            changed.push((id, task_name));
+        }
+    }
+
+    let mut msg = format!(
+        "{}件を「{}」にしました\n{}",
+        changed.len(),
+        name,
+        summary(&changed)
+    );
+    if 0 < skipped {
+        msg.push_str(&format!(
+            "\n遷移ルールで許されていない（またはコメントが必要な）{}件は変更しませんでした",
+            skipped
+        ));
+    }
+    reply(ctx, msg).await;
+    Ok(())
+}
+
+/// 条件に合うタスクの担当者をまとめて変更
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `filter` - 対象のタスク
+/// * `assignee` - 新しい担当者. 省略時は担当者なしにする
+pub async fn assign(
+    ctx: Context<'_>,
+    filter: Filter,
+    assignee: Option<serenity::User>,
+) -> Result<(), serenity::Error> {
+    let channel_id = ctx.channel_id().to_string();
+    let client = connect_to_db().await?;
+    let action = match &assignee {
+        Some(user) => format!("{} の担当にします", user.name),
+        None => "担当者なしにします".to_string(),
+    };
+    let tasks = prepare(ctx, &client, filter, &action).await?;
+
+    let member: Option<String> = assignee.map(|user| user.id.to_string());
+    let ids: Vec<uuid::Uuid> = tasks.iter().map(|(id, _)| *id).collect();
+    let query = format!(
+        "update \"{}\" set member=$1 where id = any($2);",
+        channel_id
+    );
+    match client.execute(&query, &[&member, &ids]).await {
+        Ok(count) => {
+            reply(
+                ctx,
+                format!("{}件の担当者を変更しました\n{}", count, summary(&tasks)),
+            )
+            .await
+        }
+        Err(_) => {
+            reply(ctx, "担当者を変更できませんでした").await;
+            return Err(serenity::Error::Other("担当者の変更に失敗しました"));
+        }
+    }
+    Ok(())
+}
+
+/// 条件に合うタスクをまとめて削除
+///
+/// タスクが全部無くなったらテーブルも削除する.
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `filter` - 対象のタスク
+pub async fn remove(ctx: Context<'_>, filter: Filter) -> Result<(), serenity::Error> {
+    let channel_id = ctx.channel_id().to_string();
+    let client = connect_to_db().await?;
+    let tasks = prepare(ctx, &client, filter, "削除します").await?;
+
+    let ids: Vec<uuid::Uuid> = tasks.iter().map(|(id, _)| *id).collect();
+    let query = format!("delete from \"{}\" where id = any($1);", channel_id);
+    let count = match client.execute(&query, &[&ids]).await {
+        Ok(count) => count,
+        Err(_) => {
+            reply(ctx, "タスクを削除できませんでした").await;
+            return Err(serenity::Error::Other("削除できませんでした"));
+        }
+    };
+    for id in &ids {
+        purge_task(&client, id).await;
+    }
+
+    // ---------- 0行だったらテーブルごと削除 ----------
+    let count_query = format!("select count(*) from \"{}\";", channel_id);
+    if let Ok(rows) = client.query(&count_query, &[]).await {
+        if rows[0].get::<&str, i64>("count") == 0 {
+            let _ = client
+                .execute(&format!("drop table \"{}\";", channel_id), &[])
+                .await;
+        }
+    }
+
+    board::refresh(ctx.http(), &client, &channel_id).await;
+
+    reply(
+        ctx,
+        format!("{}件を削除しました\n{}", count, summary(&tasks)),
+    )
+    .await;
+    Ok(())
+}
@@ -0,0 +1,335 @@
+//! チェックリスト関係の実装
+//!
+//! タスクの中の小さな作業をチェックリストとして持たせる.
+//! 項目は`kipp.checklist_items`に入り, 番号は追加順（1始まり）で振られる.
+
+use crate::db::{connect_to_db, find_task, task_summary};
+use crate::imp;
+use crate::support::reply;
+use crate::workflow;
+use poise::serenity_prelude::{self as serenity, CreateEmbed};
+use poise::CreateReply;
+use tokio_postgres::Client;
+
+pub type Context<'a> = poise::Context<'a, super::Data, serenity::Error>;
+
+/// チェックリストの進捗を取得
+///
+/// (完了数, 全体数)を返す. 項目が無い場合は`None`.
+pub async fn progress(client: &Client, task_id: &uuid::Uuid) -> Option<(i64, i64)> {
+    let rows = client
+        .query(
+            "select count(*) filter (where done) as done, count(*) as total
+            from kipp.checklist_items where task_id=$1;",
+            &[task_id],
+        )
+        .await
+        .ok()?;
+    let done: i64 = rows[0].get("done");
+    let total: i64 = rows[0].get("total");
+    if total == 0 {
+        None
+    } else {
+        Some((done, total))
+    }
+}
+
+/// 進捗を`3/5 ✅`の形式の文字列にする
+pub fn format_progress(done: i64, total: i64) -> String {
+    if done == total {
+        format!("{}/{} \u{2705}", done, total)
+    } else {
+        format!("{}/{}", done, total)
+    }
+}
+
+/// 番号（1始まり）から項目のIDを探す
+async fn item_id(client: &Client, task_id: &uuid::Uuid, number: i64) -> Option<i32> {
+    if number < 1 {
+        return None;
+    }
+    let rows = client
+        .query(
+            "select id from kipp.checklist_items where task_id=$1
+            order by id offset $2 limit 1;",
+            &[task_id, &(number - 1)],
+        )
+        .await
+        .ok()?;
+    rows.first().map(|row| row.get("id"))
+}
+
+/// チェックリストに項目を追加
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `task_id` - タスクのID (UUIDv4)
+/// * `content` - 項目の内容
+pub async fn add(
+    ctx: Context<'_>,
+    task_id: String,
+    content: String,
+) -> Result<(), serenity::Error> {
+    let channel_id = ctx.channel_id().to_string();
+    let client = connect_to_db().await?;
+
+    let task_id = match find_task(&client, &channel_id, &task_id).await {
+        Some(id) => id,
+        None => {
+            reply(ctx, "このチャンネルにそのタスクはありません").await;
+            return Err(serenity::Error::Other("タスクが見つかりません"));
+        }
+    };
+
+    let res = client
+        .execute(
+            "insert into kipp.checklist_items (task_id, content) values ($1, $2);",
+            &[&task_id, &content],
+        )
+        .await;
+    match res {
+        Ok(_) => {
+            reply(
+                ctx,
+                format!("「{}」をチェックリストに追加しました", content),
+            )
+            .await
+        }
+        Err(_) => {
+            reply(ctx, "チェックリストに追加できませんでした").await;
+            return Err(serenity::Error::Other("チェックリストの追加に失敗しました"));
+        }
+    }
+    Ok(())
+}
+
+/// 項目の完了/未完了を切り替える
+///
+/// 自動完了が有効で, すべての項目が完了したらタスク自体も完了済みにする.
+/// ただし遷移ルールで完了にできない（またはコメントが必要な）場合はそのままにする.
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `task_id` - タスクのID (UUIDv4)
+/// * `number` - 項目の番号
+pub async fn toggle(ctx: Context<'_>, task_id: String, number: i64) -> Result<(), serenity::Error> {
+    let channel_id = ctx.channel_id().to_string();
+    let client = connect_to_db().await?;
+
+    let task_id = match find_task(&client, &channel_id, &task_id).await {
+        Some(id) => id,
+        None => {
+            reply(ctx, "このチャンネルにそのタスクはありません").await;
+            return Err(serenity::Error::Other("タスクが見つかりません"));
+        }
+    };
+    let item = match item_id(&client, &task_id, number).await {
+        Some(id) => id,
+        None => {
+            reply(ctx, "その番号の項目はありません").await;
+            return Err(serenity::Error::Other("項目が見つかりません"));
+        }
+    };
+
+    // ---------- 切り替え ----------
+    let rows = client
+        .query(
+            "update kipp.checklist_items set done = not done where id=$1 returning done;",
+            &[&item],
+        )
+        .await;
+    let done: bool = match rows {
+        Ok(rows) => rows[0].get("done"),
+        Err(_) => {
+            reply(ctx, "項目を更新できませんでした").await;
+            return Err(serenity::Error::Other("チェックリストの更新に失敗しました"));
+        }
+    };
+
+    let (count, total) = progress(&client, &task_id).await.unwrap_or((0, 0));
+    let mut msg = format!(
+        "{}番を{}にしました（{}）",
+        number,
+        if done { "完了" } else { "未完了" },
+        format_progress(count, total)
+    );
+
+    // ---------- 自動完了 ----------
+    if done && count == total {
+        let auto = client
+            .query(
+                "select auto_complete from kipp.checklists where task_id=$1;",
+                &[&task_id],
+            )
+            .await
+            .ok()
+            .and_then(|rows| {
+                rows.first()
+                    .map(|row| row.get::<&str, bool>("auto_complete"))
+            })
+            .unwrap_or(false);
+        // 完了済みのタスクと, 遷移ルールで完了にできないタスクはそのまま
+        let current = task_summary(&client, &channel_id, &task_id)
+            .await
+            .map(|(_, status)| status)
+            .filter(|status| 0 < *status);
+        if let (true, Some(current)) = (auto, current) {
+            let guild_id = ctx.guild_id().map(|id| id.to_string());
+            let states = workflow::states(&client, &channel_id, guild_id.as_deref()).await;
+            let rules =
+                workflow::transitions(&client, &channel_id, guild_id.as_deref(), current).await;
+            match workflow::check_transition(&states, &rules, current, 0) {
+                Ok(false) => {
+                    let query = format!(
+                        "update \"{}\" set status=0 where id=$1 and status > 0;",
+                        channel_id
+                    );
+                    if let Ok(1) = client.execute(&query, &[&task_id]).await {
+                        msg.push_str("\nすべての項目が完了したのでタスクを完了済みにしました");
+                        imp::after_status_change(ctx.http(), &client, &channel_id, &task_id, 0)
+                            .await;
+                    }
+                }
+                Ok(true) => msg.push_str(
+                    "\n完了にするにはコメントが必要なので, タスクは自動で完了にしませんでした",
+                ),
+                Err(e) => msg.push_str(&format!("\n{}. タスクは自動で完了にしませんでした", e)),
+            }
+        }
+    }
+
+    reply(ctx, msg).await;
+    Ok(())
+}
+
+/// チェックリストから項目を削除
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `task_id` - タスクのID (UUIDv4)
+/// * `number` - 項目の番号
+pub async fn remove(ctx: Context<'_>, task_id: String, number: i64) -> Result<(), serenity::Error> {
+    let channel_id = ctx.channel_id().to_string();
+    let client = connect_to_db().await?;
+
+    let task_id = match find_task(&client, &channel_id, &task_id).await {
+        Some(id) => id,
+        None => {
+            reply(ctx, "このチャンネルにそのタスクはありません").await;
+            return Err(serenity::Error::Other("タスクが見つかりません"));
+        }
+    };
+    let item = match item_id(&client, &task_id, number).await {
+        Some(id) => id,
+        None => {
+            reply(ctx, "その番号の項目はありません").await;
+            return Err(serenity::Error::Other("項目が見つかりません"));
+        }
+    };
+
+    match client
+        .execute("delete from kipp.checklist_items where id=$1;", &[&item])
+        .await
+    {
+        Ok(_) => reply(ctx, format!("{}番を削除しました", number)).await,
+        Err(_) => {
+            reply(ctx, "項目を削除できませんでした").await;
+            return Err(serenity::Error::Other("チェックリストの削除に失敗しました"));
+        }
+    }
+    Ok(())
+}
+
+/// チェックリストの自動完了を切り替える
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `task_id` - タスクのID (UUIDv4)
+/// * `enabled` - すべての項目が完了したらタスクを完了済みにするか
+pub async fn auto(ctx: Context<'_>, task_id: String, enabled: bool) -> Result<(), serenity::Error> {
+    let channel_id = ctx.channel_id().to_string();
+    let client = connect_to_db().await?;
+
+    let task_id = match find_task(&client, &channel_id, &task_id).await {
+        Some(id) => id,
+        None => {
+            reply(ctx, "このチャンネルにそのタスクはありません").await;
+            return Err(serenity::Error::Other("タスクが見つかりません"));
+        }
+    };
+
+    let res = client
+        .execute(
+            "insert into kipp.checklists (task_id, auto_complete) values ($1, $2)
+            on conflict (task_id) do update set auto_complete = excluded.auto_complete;",
+            &[&task_id, &enabled],
+        )
+        .await;
+    match res {
+        Ok(_) if enabled => reply(ctx, "自動完了を有効にしました").await,
+        Ok(_) => reply(ctx, "自動完了を無効にしました").await,
+        Err(_) => {
+            reply(ctx, "設定を変更できませんでした").await;
+            return Err(serenity::Error::Other("チェックリストの設定に失敗しました"));
+        }
+    }
+    Ok(())
+}
+
+/// チェックリストを表示
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `task_id` - タスクのID (UUIDv4)
+pub async fn show(ctx: Context<'_>, task_id: String) -> Result<(), serenity::Error> {
+    let channel_id = ctx.channel_id().to_string();
+    let client = connect_to_db().await?;
+
+    let task_id = match find_task(&client, &channel_id, &task_id).await {
+        Some(id) => id,
+        None => {
+            reply(ctx, "このチャンネルにそのタスクはありません").await;
+            return Err(serenity::Error::Other("タスクが見つかりません"));
+        }
+    };
+
+    let rows = client
+        .query(
+            "select content, done from kipp.checklist_items where task_id=$1 order by id;",
+            &[&task_id],
+        )
+        .await
+        .unwrap_or_default();
+    if rows.is_empty() {
+        reply(ctx, "チェックリストはありません").await;
+        return Ok(());
+    }
+
+    let mut lines = String::new();
+    let mut done_count: i64 = 0;
+    for (i, row) in rows.iter().enumerate() {
+        let content: String = row.get("content");
+        let done: bool = row.get("done");
+        if done {
+            done_count += 1;
+        }
+        let mark = if done { "\u{2611}" } else { "\u{2610}" };
+        lines.push_str(&format!("{} {}. {}\n", mark, i + 1, content));
+    }
+
+    let embed = CreateEmbed::default()
+        .title(format!(
+            "チェックリスト {}",
+            format_progress(done_count, rows.len() as i64)
+        ))
+        .description(lines);
+    let _ = ctx
+        .send(CreateReply::default().ephemeral(true).embed(embed))
+        .await;
+    Ok(())
+}
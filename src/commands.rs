@@ -1,18 +1,34 @@
 //! 全コマンドのrootモジュール
 
 use crate::auth::auth;
+use crate::board;
+use crate::bulk;
+use crate::checklist;
+use crate::comment;
+use crate::dependency;
 use crate::disp;
+use crate::forum;
 
 use crate::imp;
+use crate::milestone;
+use crate::priority;
+use crate::recurrence;
+use crate::search;
+use crate::sprint;
 use crate::support;
-use poise::parse_slash_args;
+use crate::tag;
+use crate::template;
+use crate::timetrack;
+use crate::transfer;
+use crate::undo;
+use crate::workflow;
 use poise::serenity_prelude::{self as serenity, Error};
 
 type Context<'a> = poise::Context<'a, super::Data, serenity::Error>;
 
 // # db.rs
 
-/// # disp.rs
+// # disp.rs
 
 /// チャンネルごとにタスクの数を一覧形式で表示します。
 #[poise::command(slash_command)]
@@ -20,32 +36,83 @@ pub async fn showall(
     ctx: Context<'_>,
     #[description = "ユーザーを選択（任意）"] user: Option<serenity::User>,
     #[description = "メッセージを自分以外にも表示"] display: Option<bool>,
+    #[description = "タグで絞り込む"]
+    #[autocomplete = "tag::autocomplete_tag"]
+    tag: Option<String>,
 ) -> Result<(), Error> {
     // ---------- サーバー認証 ----------
     let _ = auth(ctx).await;
 
-    let _ = disp::showall(ctx, user, display).await;
+    let _ = disp::showall(ctx, user, display, tag).await;
+    Ok(())
+}
+
+/// 自分（または指定したユーザー）が担当している未完了のタスクを全チャンネルから表示します
+#[poise::command(slash_command)]
+pub async fn mytasks(
+    ctx: Context<'_>,
+    #[description = "ユーザー（省略時は自分）"] user: Option<serenity::User>,
+    #[description = "メッセージを自分以外にも表示"] display: Option<bool>,
+) -> Result<(), serenity::Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = disp::mytasks(ctx, user, display).await;
     Ok(())
 }
 
 /// チャンネルに属すタスクを表示
+#[allow(clippy::too_many_arguments)]
 #[poise::command(slash_command)]
 pub async fn show(
     ctx: Context<'_>,
     #[description = "ユーザーを選択（任意）"] user: Option<serenity::User>,
     #[description = "メッセージを自分以外にも表示"] display: Option<bool>,
-    #[description = "完了タスクを非表示"] is_done_print: Option<bool>,
+    #[description = "完了タスクを非表示（省略時は非表示）"] is_done_print: Option<bool>,
+    #[description = "タグで絞り込む"]
+    #[autocomplete = "tag::autocomplete_tag"]
+    tag: Option<String>,
+    #[description = "今のスプリントのタスクだけ表示"] sprint: Option<bool>,
+    #[description = "ステータスで絞り込む"]
+    #[autocomplete = "workflow::autocomplete_state"]
+    status: Option<String>,
+    #[description = "この日以前が〆切 (YYYY-MM-DD)"] due_before: Option<String>,
+    #[description = "この日以降が〆切 (YYYY-MM-DD)"] due_after: Option<String>,
+    #[description = "〆切を過ぎた未完了のタスクだけ表示"] overdue: Option<bool>,
+    #[description = "担当者なしのタスクだけ表示"] unassigned: Option<bool>,
+    #[description = "タスク名か概要に含む文字列"] contains: Option<String>,
+    #[description = "優先度で絞り込む"] priority: Option<priority::Priority>,
+    #[description = "並べ替え"] sort: Option<disp::Sort>,
+    #[description = "並べ替えの向き（省略時は昇順, 並べ替えが無ければ〆切順）"] order: Option<
+        disp::Order,
+    >,
+    #[description = "表示形式（省略時は詳細）"] view: Option<disp::View>,
 ) -> Result<(), serenity::Error> {
     // ---------- サーバー認証 ----------
     let _ = auth(ctx).await;
 
-    let _ = disp::show(ctx, user, is_done_print, display).await;
+    let opts = disp::ShowOptions {
+        tag,
+        sprint,
+        status,
+        due_before,
+        due_after,
+        overdue,
+        unassigned,
+        contains,
+        priority,
+        sort,
+        order,
+        view,
+    };
+    let _ = disp::show(ctx, user, is_done_print, display, opts).await;
     Ok(())
 }
 
 // # imp.rs
 
 /// タスクを1件追加します
+#[allow(clippy::too_many_arguments)]
 #[poise::command(slash_command)]
 pub async fn add(
     ctx: Context<'_>,
@@ -53,11 +120,30 @@ pub async fn add(
     #[description = "タスクの概要"] description: Option<String>,
     #[description = "担当者"] member: Option<serenity::Member>,
     #[description = "〆切日"] deadline: Option<String>,
+    #[description = "タグ（カンマ区切り）"]
+    #[autocomplete = "tag::autocomplete_tags"]
+    tags: Option<String>,
+    #[description = "繰り返し（daily / weekly:mon,thu / monthly:15 / cron:* * 1-5）"]
+    recurrence: Option<String>,
+    #[description = "見積もり工数（例: 1h30m）"] estimate: Option<String>,
+    #[description = "タスク用のスレッドを作成"] thread: Option<bool>,
+    #[description = "マイルストーン"]
+    #[autocomplete = "milestone::autocomplete_milestone"]
+    milestone: Option<String>,
+    #[description = "優先度"] priority: Option<priority::Priority>,
 ) -> poise::serenity_prelude::Result<(), serenity::Error> {
     // ---------- サーバー認証 ----------
     let _ = auth(ctx).await;
 
-    let _ = imp::add(ctx, task_name, description, member, deadline).await;
+    let opts = imp::AddOptions {
+        tags,
+        recurrence,
+        estimate,
+        thread,
+        milestone,
+        priority,
+    };
+    let _ = imp::add(ctx, task_name, description, member, deadline, opts).await;
     Ok(())
 }
 
@@ -80,7 +166,7 @@ pub async fn clean(ctx: Context<'_>, password: String) -> Result<(), Error> {
     // ---------- サーバー認証 ----------
     let _ = auth(ctx).await;
 
-    let _ = imp::clean(ctx.clone(), password).await;
+    let _ = imp::clean(ctx, password).await;
     Ok(())
 }
 
@@ -89,11 +175,12 @@ pub async fn clean(ctx: Context<'_>, password: String) -> Result<(), Error> {
 pub async fn status(
     ctx: Context<'_>,
     task_id: String,
+    #[description = "変更の理由など（遷移ルールで必須のことがあります）"] comment: Option<String>,
 ) -> poise::serenity_prelude::Result<(), serenity::Error> {
     // ---------- サーバー認証 ----------
     let _ = auth(ctx).await;
 
-    let _ = imp::status(ctx, task_id).await;
+    let _ = imp::status(ctx, task_id, comment).await;
     Ok(())
 }
 
@@ -118,3 +205,906 @@ pub async fn version(ctx: Context<'_>) -> Result<(), Error> {
     let _ = support::version(ctx).await;
     Ok(())
 }
+
+// # tag.rs
+
+/// タグの管理
+#[poise::command(
+    slash_command,
+    subcommands("tag_add", "tag_remove", "tag_create", "tag_delete", "tag_list"),
+    subcommand_required
+)]
+pub async fn tag(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// タスクにタグを付けます
+#[poise::command(slash_command, rename = "add")]
+pub async fn tag_add(
+    ctx: Context<'_>,
+    #[description = "タスクID"] task_id: String,
+    #[description = "タグ（カンマ区切りで複数可）"]
+    #[autocomplete = "tag::autocomplete_tags"]
+    tag: String,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = tag::add(ctx, task_id, tag).await;
+    Ok(())
+}
+
+/// タスクからタグを外します
+#[poise::command(slash_command, rename = "remove")]
+pub async fn tag_remove(
+    ctx: Context<'_>,
+    #[description = "タスクID"] task_id: String,
+    #[description = "タグ"]
+    #[autocomplete = "tag::autocomplete_tag"]
+    tag: String,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = tag::remove(ctx, task_id, tag).await;
+    Ok(())
+}
+
+/// タグを登録します（色の変更も可）
+#[poise::command(slash_command, rename = "create")]
+pub async fn tag_create(
+    ctx: Context<'_>,
+    #[description = "タグ名"] name: String,
+    #[description = "色（#RRGGBB）"] color: Option<String>,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = tag::create(ctx, name, color).await;
+    Ok(())
+}
+
+/// タグを削除します
+#[poise::command(slash_command, rename = "delete")]
+pub async fn tag_delete(
+    ctx: Context<'_>,
+    #[description = "タグ名"]
+    #[autocomplete = "tag::autocomplete_tag"]
+    name: String,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = tag::delete(ctx, name).await;
+    Ok(())
+}
+
+/// 登録済みのタグを表示します
+#[poise::command(slash_command, rename = "list")]
+pub async fn tag_list(ctx: Context<'_>) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = tag::list(ctx).await;
+    Ok(())
+}
+
+// # checklist.rs
+
+/// チェックリストの管理
+#[poise::command(
+    slash_command,
+    subcommands(
+        "checklist_add",
+        "checklist_toggle",
+        "checklist_remove",
+        "checklist_show",
+        "checklist_auto"
+    ),
+    subcommand_required
+)]
+pub async fn checklist(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// チェックリストに項目を追加します
+#[poise::command(slash_command, rename = "add")]
+pub async fn checklist_add(
+    ctx: Context<'_>,
+    #[description = "タスクID"] task_id: String,
+    #[description = "項目の内容"] content: String,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = checklist::add(ctx, task_id, content).await;
+    Ok(())
+}
+
+/// 項目の完了/未完了を切り替えます
+#[poise::command(slash_command, rename = "toggle")]
+pub async fn checklist_toggle(
+    ctx: Context<'_>,
+    #[description = "タスクID"] task_id: String,
+    #[description = "項目の番号"] number: i64,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = checklist::toggle(ctx, task_id, number).await;
+    Ok(())
+}
+
+/// チェックリストから項目を削除します
+#[poise::command(slash_command, rename = "remove")]
+pub async fn checklist_remove(
+    ctx: Context<'_>,
+    #[description = "タスクID"] task_id: String,
+    #[description = "項目の番号"] number: i64,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = checklist::remove(ctx, task_id, number).await;
+    Ok(())
+}
+
+/// チェックリストを表示します
+#[poise::command(slash_command, rename = "show")]
+pub async fn checklist_show(
+    ctx: Context<'_>,
+    #[description = "タスクID"] task_id: String,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = checklist::show(ctx, task_id).await;
+    Ok(())
+}
+
+/// 全項目完了時にタスクも完了済みにするかを設定します
+#[poise::command(slash_command, rename = "auto")]
+pub async fn checklist_auto(
+    ctx: Context<'_>,
+    #[description = "タスクID"] task_id: String,
+    #[description = "自動で完了済みにする"] enabled: bool,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = checklist::auto(ctx, task_id, enabled).await;
+    Ok(())
+}
+
+// # dependency.rs
+
+/// タスクの依存関係を追加します
+#[poise::command(slash_command)]
+pub async fn depend(
+    ctx: Context<'_>,
+    #[description = "待つ側のタスクID"] task_id: String,
+    #[description = "先に終わらせるタスクID"] on: String,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = dependency::depend(ctx, task_id, on).await;
+    Ok(())
+}
+
+/// タスクの依存関係を削除します
+#[poise::command(slash_command)]
+pub async fn undepend(
+    ctx: Context<'_>,
+    #[description = "待つ側のタスクID"] task_id: String,
+    #[description = "依存先のタスクID"] on: String,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = dependency::undepend(ctx, task_id, on).await;
+    Ok(())
+}
+
+// # recurrence.rs
+
+/// 繰り返しタスクの管理
+#[poise::command(
+    slash_command,
+    subcommands("recurring_list", "recurring_stop"),
+    subcommand_required
+)]
+pub async fn recurring(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// チャンネルの繰り返しタスクを表示します
+#[poise::command(slash_command, rename = "list")]
+pub async fn recurring_list(ctx: Context<'_>) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = recurrence::list(ctx).await;
+    Ok(())
+}
+
+/// 繰り返しを止めます
+#[poise::command(slash_command, rename = "stop")]
+pub async fn recurring_stop(
+    ctx: Context<'_>,
+    #[description = "繰り返しの番号（/recurring listで確認）"] id: i32,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = recurrence::stop(ctx, id).await;
+    Ok(())
+}
+
+// # template.rs
+
+/// タスクテンプレートの管理
+#[poise::command(
+    slash_command,
+    subcommands("template_save", "template_list", "template_delete", "template_apply"),
+    subcommand_required
+)]
+pub async fn template(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// チャンネルの未完了タスクをテンプレートとして保存します
+#[poise::command(slash_command, rename = "save")]
+pub async fn template_save(
+    ctx: Context<'_>,
+    #[description = "テンプレート名"]
+    #[autocomplete = "template::autocomplete_template"]
+    name: String,
+    #[description = "〆切の基準日（YYYY-MM-DD, 省略時は今日）"] base_date: Option<String>,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = template::save(ctx, name, base_date).await;
+    Ok(())
+}
+
+/// テンプレートの一覧を表示します
+#[poise::command(slash_command, rename = "list")]
+pub async fn template_list(
+    ctx: Context<'_>,
+    #[description = "中身を表示するテンプレート（任意）"]
+    #[autocomplete = "template::autocomplete_template"]
+    name: Option<String>,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = template::list(ctx, name).await;
+    Ok(())
+}
+
+/// テンプレートを削除します
+#[poise::command(slash_command, rename = "delete")]
+pub async fn template_delete(
+    ctx: Context<'_>,
+    #[description = "テンプレート名"]
+    #[autocomplete = "template::autocomplete_template"]
+    name: String,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = template::delete(ctx, name).await;
+    Ok(())
+}
+
+/// テンプレートのタスクをこのチャンネルに作成します
+#[poise::command(slash_command, rename = "apply")]
+pub async fn template_apply(
+    ctx: Context<'_>,
+    #[description = "テンプレート名"]
+    #[autocomplete = "template::autocomplete_template"]
+    name: String,
+    #[description = "〆切の基準日（YYYY-MM-DD, 省略時は今日）"] base_date: Option<String>,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = template::apply(ctx, name, base_date).await;
+    Ok(())
+}
+
+// # timetrack.rs
+
+/// 作業タイマー
+#[poise::command(
+    slash_command,
+    subcommands("timer_start", "timer_stop"),
+    subcommand_required
+)]
+pub async fn timer(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// タスクの作業タイマーを開始します
+#[poise::command(slash_command, rename = "start")]
+pub async fn timer_start(
+    ctx: Context<'_>,
+    #[description = "タスクID"] task_id: String,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = timetrack::start(ctx, task_id).await;
+    Ok(())
+}
+
+/// タイマーを止めて作業時間を記録します
+#[poise::command(slash_command, rename = "stop")]
+pub async fn timer_stop(ctx: Context<'_>) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = timetrack::stop(ctx).await;
+    Ok(())
+}
+
+/// 作業時間を手入力で記録します
+#[poise::command(slash_command)]
+pub async fn log(
+    ctx: Context<'_>,
+    #[description = "タスクID"] task_id: String,
+    #[description = "作業時間（例: 1h30m）"] duration: String,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = timetrack::log(ctx, task_id, duration).await;
+    Ok(())
+}
+
+/// タスクの見積もり工数を設定します
+#[poise::command(slash_command)]
+pub async fn estimate(
+    ctx: Context<'_>,
+    #[description = "タスクID"] task_id: String,
+    #[description = "見積もり（例: 1h30m）"] duration: String,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = timetrack::estimate(ctx, task_id, duration).await;
+    Ok(())
+}
+
+/// 期間内の作業時間を集計します
+#[poise::command(slash_command)]
+pub async fn report(
+    ctx: Context<'_>,
+    #[description = "ユーザー（省略時は自分）"] user: Option<serenity::User>,
+    #[description = "開始日（YYYY-MM-DD, 省略時は今月1日）"] from: Option<String>,
+    #[description = "終了日（YYYY-MM-DD, 省略時は今日）"] to: Option<String>,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = timetrack::report(ctx, user, from, to).await;
+    Ok(())
+}
+
+// # comment.rs
+
+/// タスクにコメントします
+#[poise::command(slash_command)]
+pub async fn comment(
+    ctx: Context<'_>,
+    #[description = "タスクID"] task_id: String,
+    #[description = "コメント"] text: String,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = comment::add(ctx, task_id, text).await;
+    Ok(())
+}
+
+/// タスクのコメントを表示します
+#[poise::command(slash_command)]
+pub async fn comments(
+    ctx: Context<'_>,
+    #[description = "タスクID"] task_id: String,
+    #[description = "メッセージを自分以外にも表示"] display: Option<bool>,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = comment::list(ctx, task_id, display).await;
+    Ok(())
+}
+
+// # forum.rs
+
+/// フォーラムチャンネルとの連携
+#[poise::command(
+    slash_command,
+    subcommands("forum_link", "forum_unlink"),
+    subcommand_required
+)]
+pub async fn forum(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// フォーラムをタスクボードにします（投稿1つがタスク1件になります）
+#[poise::command(slash_command, rename = "link")]
+pub async fn forum_link(
+    ctx: Context<'_>,
+    #[description = "フォーラムチャンネル"]
+    #[channel_types("Forum")]
+    forum: serenity::GuildChannel,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = forum::link(ctx, forum).await;
+    Ok(())
+}
+
+/// フォーラムの連携を解除します
+#[poise::command(slash_command, rename = "unlink")]
+pub async fn forum_unlink(
+    ctx: Context<'_>,
+    #[description = "フォーラムチャンネル"]
+    #[channel_types("Forum")]
+    forum: serenity::GuildChannel,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = forum::unlink(ctx, forum).await;
+    Ok(())
+}
+
+// # transfer.rs
+
+/// タスクを別のチャンネルへ移動します（タスクIDはそのまま）
+#[poise::command(slash_command, rename = "move")]
+pub async fn move_task(
+    ctx: Context<'_>,
+    #[description = "タスクID"] task_id: String,
+    #[description = "移動先のチャンネル"]
+    #[channel_types("Text", "PublicThread", "PrivateThread")]
+    channel: serenity::GuildChannel,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = transfer::move_task(ctx, task_id, channel).await;
+    Ok(())
+}
+
+/// タスクを別のチャンネルへコピーします
+#[poise::command(slash_command)]
+pub async fn copy(
+    ctx: Context<'_>,
+    #[description = "タスクID"] task_id: String,
+    #[description = "コピー先のチャンネル"]
+    #[channel_types("Text", "PublicThread", "PrivateThread")]
+    channel: serenity::GuildChannel,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = transfer::copy_task(ctx, task_id, channel).await;
+    Ok(())
+}
+
+/// このチャンネルのタスクをすべて別のチャンネルへ移動します
+#[poise::command(slash_command)]
+pub async fn moveall(
+    ctx: Context<'_>,
+    #[description = "移動先のチャンネル"]
+    #[channel_types("Text", "PublicThread", "PrivateThread")]
+    channel: serenity::GuildChannel,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = transfer::move_all(ctx, channel).await;
+    Ok(())
+}
+
+// # bulk.rs
+
+/// 複数のタスクをまとめて操作
+#[poise::command(
+    slash_command,
+    subcommands("bulk_status", "bulk_assign", "bulk_remove"),
+    subcommand_required
+)]
+pub async fn bulk(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// 条件に合うタスクのステータスをまとめて変更します
+#[poise::command(slash_command, rename = "status")]
+pub async fn bulk_status(
+    ctx: Context<'_>,
+    #[description = "変更後のステータス"]
+    #[autocomplete = "workflow::autocomplete_state"]
+    status: String,
+    #[description = "タスクID（カンマ区切りで複数）"] task_ids: Option<String>,
+    #[description = "担当者で絞り込む"] member: Option<serenity::User>,
+    #[description = "〆切を過ぎた未完了のタスク"] overdue: Option<bool>,
+    #[description = "タグで絞り込む"]
+    #[autocomplete = "tag::autocomplete_tag"]
+    tag: Option<String>,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let filter = bulk::Filter {
+        task_ids,
+        member,
+        overdue,
+        tag,
+    };
+    let _ = bulk::status(ctx, filter, status).await;
+    Ok(())
+}
+
+/// 条件に合うタスクの担当者をまとめて変更します
+#[poise::command(slash_command, rename = "assign")]
+pub async fn bulk_assign(
+    ctx: Context<'_>,
+    #[description = "新しい担当者（省略時は担当者なし）"] assignee: Option<serenity::User>,
+    #[description = "タスクID（カンマ区切りで複数）"] task_ids: Option<String>,
+    #[description = "担当者で絞り込む"] member: Option<serenity::User>,
+    #[description = "〆切を過ぎた未完了のタスク"] overdue: Option<bool>,
+    #[description = "タグで絞り込む"]
+    #[autocomplete = "tag::autocomplete_tag"]
+    tag: Option<String>,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let filter = bulk::Filter {
+        task_ids,
+        member,
+        overdue,
+        tag,
+    };
+    let _ = bulk::assign(ctx, filter, assignee).await;
+    Ok(())
+}
+
+/// 条件に合うタスクをまとめて削除します
+#[poise::command(slash_command, rename = "remove")]
+pub async fn bulk_remove(
+    ctx: Context<'_>,
+    #[description = "タスクID（カンマ区切りで複数）"] task_ids: Option<String>,
+    #[description = "担当者で絞り込む"] member: Option<serenity::User>,
+    #[description = "〆切を過ぎた未完了のタスク"] overdue: Option<bool>,
+    #[description = "タグで絞り込む"]
+    #[autocomplete = "tag::autocomplete_tag"]
+    tag: Option<String>,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let filter = bulk::Filter {
+        task_ids,
+        member,
+        overdue,
+        tag,
+    };
+    let _ = bulk::remove(ctx, filter).await;
+    Ok(())
+}
+
+// # undo.rs
+
+/// 直前の /status か /remove を元に戻します（5分以内）
+#[poise::command(slash_command)]
+pub async fn undo(ctx: Context<'_>) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = undo::undo(ctx).await;
+    Ok(())
+}
+
+// # workflow.rs
+
+/// ステータスのワークフローの設定
+#[poise::command(
+    slash_command,
+    subcommands(
+        "workflow_set",
+        "workflow_show",
+        "workflow_allow",
+        "workflow_disallow",
+        "workflow_reset"
+    ),
+    subcommand_required
+)]
+pub async fn workflow(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// ワークフローを設定します（チャンネル管理の権限が必要）
+#[poise::command(
+    slash_command,
+    rename = "set",
+    required_permissions = "MANAGE_CHANNELS"
+)]
+pub async fn workflow_set(
+    ctx: Context<'_>,
+    #[description = "状態の並び（例: 未着手, 進行中 #00ff00, 要確認 #ffaa00, 完了済み）"]
+    states: String,
+    #[description = "完了扱いにする状態（カンマ区切り, 省略時は最後の状態）"] done: Option<String>,
+    #[description = "設定範囲（省略時はこのチャンネル）"] scope: Option<workflow::Scope>,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = workflow::set(ctx, states, done, scope).await;
+    Ok(())
+}
+
+/// このチャンネルのワークフローを表示します
+#[poise::command(slash_command, rename = "show")]
+pub async fn workflow_show(ctx: Context<'_>) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = workflow::show(ctx).await;
+    Ok(())
+}
+
+/// 遷移ルールを追加します（チャンネル管理の権限が必要）
+#[poise::command(
+    slash_command,
+    rename = "allow",
+    required_permissions = "MANAGE_CHANNELS"
+)]
+pub async fn workflow_allow(
+    ctx: Context<'_>,
+    #[description = "元の状態"]
+    #[autocomplete = "workflow::autocomplete_state"]
+    from: String,
+    #[description = "移れる先の状態（カンマ区切りで複数）"] to: String,
+    #[description = "移るときにコメントを必須にする"] require_comment: Option<bool>,
+    #[description = "設定範囲（省略時はこのチャンネル）"] scope: Option<workflow::Scope>,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = workflow::allow(ctx, from, to, require_comment, scope).await;
+    Ok(())
+}
+
+/// 遷移ルールを削除します（チャンネル管理の権限が必要）
+#[poise::command(
+    slash_command,
+    rename = "disallow",
+    required_permissions = "MANAGE_CHANNELS"
+)]
+pub async fn workflow_disallow(
+    ctx: Context<'_>,
+    #[description = "元の状態"]
+    #[autocomplete = "workflow::autocomplete_state"]
+    from: String,
+    #[description = "消すルールの移れる先（カンマ区切り, 省略時はすべて）"] to: Option<String>,
+    #[description = "設定範囲（省略時はこのチャンネル）"] scope: Option<workflow::Scope>,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = workflow::disallow(ctx, from, to, scope).await;
+    Ok(())
+}
+
+/// ワークフローの設定を消して既定に戻します（チャンネル管理の権限が必要）
+#[poise::command(
+    slash_command,
+    rename = "reset",
+    required_permissions = "MANAGE_CHANNELS"
+)]
+pub async fn workflow_reset(
+    ctx: Context<'_>,
+    #[description = "設定範囲（省略時はこのチャンネル）"] scope: Option<workflow::Scope>,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = workflow::reset(ctx, scope).await;
+    Ok(())
+}
+
+// # milestone.rs
+
+/// マイルストーンを管理します
+#[poise::command(
+    slash_command,
+    subcommands(
+        "milestone_create",
+        "milestone_list",
+        "milestone_show",
+        "milestone_close",
+        "milestone_assign"
+    ),
+    subcommand_required
+)]
+pub async fn milestone(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// マイルストーンを作成します
+#[poise::command(slash_command, rename = "create")]
+pub async fn milestone_create(
+    ctx: Context<'_>,
+    #[description = "マイルストーン名"] name: String,
+    #[description = "〆切日 (YYYY-MM-DD)"] due: Option<String>,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = milestone::create(ctx, name, due).await;
+    Ok(())
+}
+
+/// マイルストーンの一覧を進捗つきで表示します
+#[poise::command(slash_command, rename = "list")]
+pub async fn milestone_list(
+    ctx: Context<'_>,
+    #[description = "閉じたマイルストーンも表示"] closed: Option<bool>,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = milestone::list(ctx, closed).await;
+    Ok(())
+}
+
+/// マイルストーンの進捗を表示します
+#[poise::command(slash_command, rename = "show")]
+pub async fn milestone_show(
+    ctx: Context<'_>,
+    #[description = "マイルストーン名"]
+    #[autocomplete = "milestone::autocomplete_milestone"]
+    name: String,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = milestone::show(ctx, name).await;
+    Ok(())
+}
+
+/// マイルストーンを閉じます
+#[poise::command(slash_command, rename = "close")]
+pub async fn milestone_close(
+    ctx: Context<'_>,
+    #[description = "マイルストーン名"]
+    #[autocomplete = "milestone::autocomplete_milestone"]
+    name: String,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = milestone::close(ctx, name).await;
+    Ok(())
+}
+
+/// タスクをマイルストーンに入れます（省略時は外します）
+#[poise::command(slash_command, rename = "assign")]
+pub async fn milestone_assign(
+    ctx: Context<'_>,
+    #[description = "タスクID"] task_id: String,
+    #[description = "マイルストーン名（省略時はマイルストーンから外す）"]
+    #[autocomplete = "milestone::autocomplete_milestone"]
+    name: Option<String>,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = milestone::assign(ctx, task_id, name).await;
+    Ok(())
+}
+
+// # sprint.rs
+
+/// このチャンネルのスプリントを管理します
+#[poise::command(
+    slash_command,
+    subcommands("sprint_start", "sprint_add", "sprint_end"),
+    subcommand_required
+)]
+pub async fn sprint(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// スプリントを開始します
+#[poise::command(slash_command, rename = "start")]
+pub async fn sprint_start(
+    ctx: Context<'_>,
+    #[description = "スプリント名"] name: String,
+    #[description = "終了日 (YYYY-MM-DD)"] end_date: String,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = sprint::start(ctx, name, end_date).await;
+    Ok(())
+}
+
+/// タスクを今のスプリントに入れます
+#[poise::command(slash_command, rename = "add")]
+pub async fn sprint_add(
+    ctx: Context<'_>,
+    #[description = "タスクID"] task_id: String,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = sprint::add(ctx, task_id).await;
+    Ok(())
+}
+
+/// スプリントを終了して結果を報告します
+#[poise::command(slash_command, rename = "end")]
+pub async fn sprint_end(
+    ctx: Context<'_>,
+    #[description = "次のスプリント名（未完了のタスクを持ち越す）"] next_name: Option<String>,
+    #[description = "次のスプリントの終了日 (YYYY-MM-DD)"] next_end_date: Option<String>,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = sprint::end(ctx, next_name, next_end_date).await;
+    Ok(())
+}
+
+// # priority.rs
+
+/// タスクの優先度を設定します
+#[poise::command(slash_command)]
+pub async fn priority(
+    ctx: Context<'_>,
+    #[description = "タスクID"] task_id: String,
+    #[description = "優先度（省略時は未設定に戻す）"] priority: Option<priority::Priority>,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = priority::set(ctx, task_id, priority).await;
+    Ok(())
+}
+
+// # board.rs
+
+/// チャンネルにタスクボードを投稿してピン留めします
+#[poise::command(slash_command)]
+pub async fn board(ctx: Context<'_>) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = board::post(ctx).await;
+    Ok(())
+}
+
+// # search.rs
+
+/// タスク名・概要・コメントからタスクを検索します
+#[poise::command(slash_command)]
+pub async fn search(
+    ctx: Context<'_>,
+    #[description = "検索する文字列"] query: String,
+) -> Result<(), Error> {
+    // ---------- サーバー認証 ----------
+    let _ = auth(ctx).await;
+
+    let _ = search::search(ctx, query).await;
+    Ok(())
+}
@@ -0,0 +1,184 @@
+//! タスクへのコメントの実装
+//!
+//! コメントは`kipp.comments`に投稿者と日時つきで保存する.
+//! `/comment`のほか, Botのタスク表示メッセージへの返信もコメントとして取り込める
+//! (`.env`で`CAPTURE_REPLIES=true`のとき).
+
+use crate::db::{connect_to_db, find_task, locate_task};
+use crate::disp::{line_pages, paginate};
+use crate::support::reply;
+use chrono::{DateTime, Local};
+use poise::serenity_prelude::{self as serenity, Message, ReactionType};
+use tokio_postgres::Client;
+
+pub type Context<'a> = poise::Context<'a, super::Data, serenity::Error>;
+
+/// `/comments`で表示する最大件数（新しい順）
+const SHOW_LIMIT: i64 = 20;
+
+/// タスクのコメント数
+pub async fn count(client: &Client, task_id: &uuid::Uuid) -> i64 {
+    client
+        .query(
+            "select count(*) from kipp.comments where task_id=$1;",
+            &[task_id],
+        )
+        .await
+        .map(|rows| rows[0].get("count"))
+        .unwrap_or(0)
+}
+
+/// コメントを保存する
+pub async fn insert(
+    client: &Client,
+    task_id: &uuid::Uuid,
+    author: &str,
+    content: &str,
+) -> Result<(), tokio_postgres::Error> {
+    client
+        .execute(
+            "insert into kipp.comments (task_id, author, content) values ($1, $2, $3);",
+            &[task_id, &author, &content],
+        )
+        .await?;
+    Ok(())
+}
+
+/// タスクにコメントする
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `task_id` - タスクのID (UUIDv4)
+/// * `text` - コメント本文
+pub async fn add(ctx: Context<'_>, task_id: String, text: String) -> Result<(), serenity::Error> {
+    let channel_id = ctx.channel_id().to_string();
+    let author = ctx.author().id.to_string();
+    let client = connect_to_db().await?;
+
+    let task_id = match find_task(&client, &channel_id, &task_id).await {
+        Some(id) => id,
+        None => {
+            reply(ctx, "このチャンネルにそのタスクはありません").await;
+            return Err(serenity::Error::Other("タスクが見つかりません"));
+        }
+    };
+
+    match insert(&client, &task_id, &author, text.trim()).await {
+        Ok(_) => reply(ctx, "コメントしました").await,
+        Err(_) => {
+            reply(ctx, "コメントできませんでした").await;
+            return Err(serenity::Error::Other("コメントの登録に失敗しました"));
+        }
+    }
+    Ok(())
+}
+
+/// タスクのコメントを新しい順に表示する
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `task_id` - タスクのID (UUIDv4)
+/// * `display` - 自分以外にも表示させる場合は`true`
+pub async fn list(
+    ctx: Context<'_>,
+    task_id: String,
+    display: Option<bool>,
+) -> Result<(), serenity::Error> {
+    let channel_id = ctx.channel_id().to_string();
+    let client = connect_to_db().await?;
+    let is_disp = if let Some(b) = display { !b } else { true };
+
+    let task_id = match find_task(&client, &channel_id, &task_id).await {
+        Some(id) => id,
+        None => {
+            reply(ctx, "このチャンネルにそのタスクはありません").await;
+            return Err(serenity::Error::Other("タスクが見つかりません"));
+        }
+    };
+
+    let rows = client
+        .query(
+            "select author, content, created_at from kipp.comments
+            where task_id=$1 order by created_at desc limit $2;",
+            &[&task_id, &SHOW_LIMIT],
+        )
+        .await
+        .unwrap_or_default();
+    if rows.is_empty() {
+        reply(ctx, "コメントはありません").await;
+        return Ok(());
+    }
+
+    let total = count(&client, &task_id).await;
+    let lines = rows.iter().map(|row| {
+        let author: String = row.get("author");
+        let content: String = row.get("content");
+        let created_at: DateTime<Local> =
+            row.get::<&str, DateTime<chrono::Utc>>("created_at").into();
+        // 1件で説明文を使い切らないように切り詰める
+        let content: String = content.chars().take(1000).collect();
+        format!(
+            "**{}** <@{}>\n{}\n",
+            created_at.format("%Y-%m-%d %H:%M"),
+            author,
+            content
+        )
+    });
+
+    // 合計6000文字を超えないようにページに分ける
+    paginate(
+        ctx,
+        line_pages(&format!("コメント ({}件)", total), lines),
+        is_disp,
+    )
+    .await;
+    Ok(())
+}
+
+/// Botのタスク表示メッセージへの返信をコメントとして取り込む
+///
+/// 返信先の埋め込みの「タスクID」フィールドからタスクを特定する.
+/// 「タスクID」のある埋め込みがちょうど1つのメッセージへの返信だけを取り込む.
+/// 取り込めたら返信に💬のリアクションを付ける.
+pub async fn capture_reply(ctx: &serenity::Context, bot_id: serenity::UserId, msg: &Message) {
+    let Some(referenced) = &msg.referenced_message else {
+        return;
+    };
+    if referenced.author.id != bot_id || msg.author.bot || msg.content.trim().is_empty() {
+        return;
+    }
+
+    // ---------- 返信先の埋め込みからタスクIDを探す ----------
+    // NOTE: `/show`のように複数のタスクが並ぶメッセージではどれへの返信か分からないので取り込まない
+    let task_ids: Vec<&str> = referenced
+        .embeds
+        .iter()
+        .filter_map(|embed| embed.fields.iter().find(|field| field.name == "タスクID"))
+        .map(|field| field.value.trim())
+        .collect();
+    let [task_id] = task_ids[..] else {
+        return;
+    };
+    let Ok(task_id) = uuid::Uuid::parse_str(task_id) else {
+        return;
+    };
+
+    let Ok(client) = connect_to_db().await else {
+        return;
+    };
+    if locate_task(&client, &task_id).await.is_none() {
+        return;
+    }
+
+    let author = msg.author.id.to_string();
+    if insert(&client, &task_id, &author, msg.content.trim())
+        .await
+        .is_ok()
+    {
+        let _ = msg
+            .react(ctx, ReactionType::Unicode("\u{1f4ac}".to_string()))
+            .await;
+    }
+}
@@ -4,9 +4,222 @@ use poise::serenity_prelude::*;
 
 // タイムアウト処理用
 use super::*;
-use tokio;
 use tokio_postgres::{Client, Error};
 
+/// Bot起動時に流すクエリ
+///
+/// チャンネルごとのタスクテーブルは`public`スキーマに作られるので,
+/// それ以外の管理用テーブルはすべて`kipp`スキーマに置く.
+///
+/// `public`以外に置いておけば`showall`や`clean`がチャンネルと勘違いしない.
+const INIT_QUERY: &str = "
+    create schema if not exists kipp;
+
+    create table if not exists kipp.tags (
+        guild_id text NOT NULL,
+        name text NOT NULL,
+        color integer,
+        primary key (guild_id, name));
+
+    create table if not exists kipp.task_tags (
+        task_id uuid NOT NULL,
+        tag text NOT NULL,
+        primary key (task_id, tag));
+
+    create table if not exists kipp.checklists (
+        task_id uuid primary key,
+        auto_complete boolean NOT NULL DEFAULT false);
+
+    create table if not exists kipp.checklist_items (
+        id serial primary key,
+        task_id uuid NOT NULL,
+        content text NOT NULL,
+        done boolean NOT NULL DEFAULT false);
+
+    create table if not exists kipp.dependencies (
+        task_id uuid NOT NULL,
+        depends_on uuid NOT NULL,
+        primary key (task_id, depends_on));
+
+    create table if not exists kipp.recurrences (
+        id serial primary key,
+        channel_id text NOT NULL,
+        task_id uuid NOT NULL,
+        rule text NOT NULL);
+
+    create table if not exists kipp.templates (
+        guild_id text NOT NULL,
+        name text NOT NULL,
+        primary key (guild_id, name));
+
+    create table if not exists kipp.template_items (
+        id serial primary key,
+        guild_id text NOT NULL,
+        template text NOT NULL,
+        task_name text NOT NULL,
+        description text,
+        member text,
+        offset_days integer);
+
+    create table if not exists kipp.timers (
+        user_id text primary key,
+        task_id uuid NOT NULL,
+        started_at timestamptz NOT NULL DEFAULT now());
+
+    create table if not exists kipp.time_logs (
+        id serial primary key,
+        task_id uuid NOT NULL,
+        user_id text NOT NULL,
+        started_at timestamptz NOT NULL,
+        ended_at timestamptz NOT NULL,
+        minutes integer NOT NULL);
+
+    create table if not exists kipp.comments (
+        id serial primary key,
+        task_id uuid NOT NULL,
+        author text NOT NULL,
+        content text NOT NULL,
+        created_at timestamptz NOT NULL DEFAULT now());
+
+    create table if not exists kipp.forum_boards (
+        forum_id text primary key,
+        guild_id text NOT NULL,
+        todo_tag text NOT NULL,
+        doing_tag text NOT NULL,
+        done_tag text NOT NULL);
+
+    create table if not exists kipp.undo (
+        user_id text primary key,
+        channel_id text NOT NULL,
+        task_id uuid NOT NULL,
+        action text NOT NULL,
+        snapshot jsonb NOT NULL,
+        created_at timestamptz NOT NULL DEFAULT now());
+
+    create table if not exists kipp.workflow_states (
+        scope_id text NOT NULL,
+        guild_id text NOT NULL,
+        position smallint NOT NULL,
+        code smallint NOT NULL,
+        name text NOT NULL,
+        color integer,
+        done boolean NOT NULL DEFAULT false,
+        primary key (scope_id, code));
+
+    create table if not exists kipp.workflow_transitions (
+        scope_id text NOT NULL,
+        from_code smallint NOT NULL,
+        to_code smallint NOT NULL,
+        require_comment boolean NOT NULL DEFAULT false,
+        primary key (scope_id, from_code, to_code));
+
+    create table if not exists kipp.forum_posts (
+        post_id text primary key,
+        forum_id text NOT NULL);
+
+    create table if not exists kipp.milestones (
+        id serial primary key,
+        guild_id text NOT NULL,
+        name text NOT NULL,
+        due date,
+        closed boolean NOT NULL DEFAULT false,
+        unique (guild_id, name));
+
+    create table if not exists kipp.milestone_tasks (
+        task_id uuid primary key,
+        milestone_id integer NOT NULL,
+        channel_id text);
+
+    -- 後から追加した列. 追加前の紐付けは空のまま
+    alter table kipp.milestone_tasks add column if not exists channel_id text;
+
+    create table if not exists kipp.sprints (
+        id serial primary key,
+        channel_id text NOT NULL,
+        name text NOT NULL,
+        start_date date NOT NULL DEFAULT current_date,
+        end_date date NOT NULL,
+        ended boolean NOT NULL DEFAULT false,
+        ended_at date);
+
+    create table if not exists kipp.boards (
+        channel_id text primary key,
+        message_id text NOT NULL);
+
+    create unique index if not exists sprints_active
+        on kipp.sprints (channel_id) where not ended;
+";
+
+/// 検索用の拡張とコメントのインデックスを作るクエリ
+///
+/// 日本語は単語に分けられないので, 全文検索ではなくトライグラム(`pg_trgm`)で探す.
+const SEARCH_QUERY: &str = "
+    create extension if not exists pg_trgm;
+
+    create index if not exists comments_search
+        on kipp.comments using gin (content gin_trgm_ops);
+";
+
+/// 後から追加したタスクテーブルの列
+///
+/// 既存のテーブルにはBot起動時に`add column if not exists`で追加される.
+/// 新しく作るテーブルにも同じ方法で追加する.
+const TASK_COLUMNS: &[&str] = &[
+    // 見積もり工数（分）
+    "estimate integer",
+    // タスク用スレッドのチャンネルID
+    "thread_id text",
+    // 入っているスプリント（`kipp.sprints`のID）
+    "sprint_id integer",
+    // 優先度（大きいほど高い）
+    "priority smallint",
+    // 作成日時. 列を追加する前からあるタスクは追加した日時になる
+    "created_at timestamptz DEFAULT now()",
+];
+
+/// 検索対象にするタスクの文字列
+///
+/// 検索用のインデックスと同じ式にしないとインデックスが使われない.
+pub const SEARCH_TEXT: &str = "(task_name || ' ' || coalesce(description, ''))";
+
+/// タスクテーブルに足りない列を追加する
+///
+/// 検索用のインデックスも作る. `pg_trgm`が使えない環境もあるのでインデックスの失敗は無視する.
+pub async fn migrate_task_table(client: &Client, channel_id: &str) -> Result<(), Error> {
+    for column in TASK_COLUMNS {
+        let query = format!(
+            "alter table \"{}\" add column if not exists {};",
+            channel_id, column
+        );
+        client.execute(&query, &[]).await?;
+    }
+    let index = format!(
+        "create index if not exists \"{0}_search\" on \"{0}\" using gin ({1} gin_trgm_ops);",
+        channel_id, SEARCH_TEXT
+    );
+    let _ = client.execute(&index, &[]).await;
+    Ok(())
+}
+
+/// チャンネルのタスクテーブルを作るクエリ
+///
+/// NOTE: ステータス
+/// - 進行中 = 2
+/// - 未着手 = 1
+/// - 完了済み = 0
+fn create_task_table_query(channel_id: &str) -> String {
+    format!(
+        "create table if not exists \"{}\" (\
+            id uuid DEFAULT uuid_generate_v4(), \
+            task_name text NOT NULL, \
+            description text,\
+            member text, \
+            deadline date, \
+            status smallint DEFAULT 1);",
+        channel_id
+    )
+}
+
 /// データベースへの接続を確立する処理
 ///
 ///
@@ -37,7 +250,186 @@ pub async fn connect_to_db() -> Result<Client, serenity::Error> {
         Ok(result) => Ok(result),
         Err(e) => {
             eprintln!("Connected error: {}", e);
-            return Err(serenity::Error::Other("Database connection error".into()));
+            Err(serenity::Error::Other("Database connection error"))
         }
     }
 }
+
+/// 管理用テーブルの作成
+///
+/// Bot起動時に一度だけ呼ぶ. 既にテーブルがある場合は何もしない.
+pub async fn init() -> Result<(), serenity::Error> {
+    let client = connect_to_db().await?;
+
+    if let Err(e) = client.batch_execute(INIT_QUERY).await {
+        eprintln!("Init error: {}", e);
+        return Err(serenity::Error::Other("Database init error"));
+    }
+
+    // ---------- 検索用の拡張 ----------
+    // 入れられなくても検索が遅くなるだけなので続ける
+    if let Err(e) = client.batch_execute(SEARCH_QUERY).await {
+        eprintln!("Search index error: {}", e);
+    }
+
+    // ---------- 既存のタスクテーブルに列を追加 ----------
+    let tables = task_tables(&client).await.unwrap_or_default();
+    for table in tables {
+        if let Err(e) = migrate_task_table(&client, &table).await {
+            eprintln!("Migration error ({}): {}", table, e);
+        }
+    }
+    Ok(())
+}
+
+/// タスクテーブル（=チャンネルID）の一覧を取得する
+///
+/// `public`スキーマのテーブルだけを返す. "{}"という名前のテーブルは除く.
+pub async fn task_tables(client: &Client) -> Result<Vec<String>, Error> {
+    let rows = client
+        .query(
+            "select tablename from pg_tables
+            where schemaname = 'public'
+            order by tablename;",
+            &[],
+        )
+        .await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| row.get::<&str, String>("tablename"))
+        .filter(|tb| tb != "{}")
+        .collect())
+}
+
+/// チャンネルのテーブルにタスクが存在するかどうか
+///
+/// テーブル自体が無い場合も`false`を返す.
+pub async fn task_exists(client: &Client, channel_id: &str, task_id: &uuid::Uuid) -> bool {
+    let query = format!("select count(*) from \"{}\" where id=$1;", channel_id);
+    match client.query(&query, &[task_id]).await {
+        Ok(rows) => 0 < rows[0].get::<&str, i64>("count"),
+        Err(_) => false,
+    }
+}
+
+/// 入力されたタスクIDを解釈し, チャンネルのテーブルに存在すればUUIDを返す
+pub async fn find_task(client: &Client, channel_id: &str, task_id: &str) -> Option<uuid::Uuid> {
+    match uuid::Uuid::parse_str(task_id.trim()) {
+        Ok(id) if task_exists(client, channel_id, &id).await => Some(id),
+        _ => None,
+    }
+}
+
+/// タスクに紐付いた管理用テーブルの行を削除する
+///
+/// タスクを削除したときに呼ぶ. 作業記録(`kipp.time_logs`)は集計用に残す.
+pub async fn purge_task(client: &Client, task_id: &uuid::Uuid) {
+    for table in [
+        "kipp.task_tags",
+        "kipp.checklist_items",
+        "kipp.checklists",
+        "kipp.recurrences",
+        "kipp.timers",
+        "kipp.comments",
+        "kipp.milestone_tasks",
+    ] {
+        let query = format!("delete from {} where task_id=$1;", table);
+        let _ = client.execute(&query, &[task_id]).await;
+    }
+    let _ = client
+        .execute(
+            "delete from kipp.dependencies where task_id=$1 or depends_on=$1;",
+            &[task_id],
+        )
+        .await;
+}
+
+/// タスクがどのチャンネルのテーブルにあるかを探す
+///
+/// 見つかったらチャンネルIDを返す.
+pub async fn locate_task(client: &Client, task_id: &uuid::Uuid) -> Option<String> {
+    let tables = task_tables(client).await.ok()?;
+    for table in tables {
+        if task_exists(client, &table, task_id).await {
+            return Some(table);
+        }
+    }
+    None
+}
+
+/// タスクの(タスク名, ステータス)を取得する
+pub async fn task_summary(
+    client: &Client,
+    channel_id: &str,
+    task_id: &uuid::Uuid,
+) -> Option<(String, i16)> {
+    let query = format!(
+        "select task_name, status from \"{}\" where id=$1;",
+        channel_id
+    );
+    let rows = client.query(&query, &[task_id]).await.ok()?;
+    rows.first()
+        .map(|row| (row.get("task_name"), row.get("status")))
+}
+
+/// タスクテーブルの全列名
+///
+/// 元からある列の後ろに`TASK_COLUMNS`の列名が続く.
+pub fn task_column_names() -> Vec<&'static str> {
+    let mut names = vec![
+        "id",
+        "task_name",
+        "description",
+        "member",
+        "deadline",
+        "status",
+    ];
+    names.extend(
+        TASK_COLUMNS
+            .iter()
+            .filter_map(|column| column.split_whitespace().next()),
+    );
+    names
+}
+
+/// チャンネルのタスクテーブルが無ければ作成し, 列を揃える
+pub async fn ensure_task_table(client: &Client, channel_id: &str) -> Result<(), Error> {
+    client
+        .execute(&create_task_table_query(channel_id), &[])
+        .await?;
+    migrate_task_table(client, channel_id).await
+}
+
+/// チャンネルのテーブルにタスクを1件登録する
+///
+/// テーブルが無ければ作成してから登録する. 登録したタスクのIDを返す.
+pub async fn insert_task(
+    client: &Client,
+    channel_id: &str,
+    task_name: &str,
+    description: &Option<String>,
+    member: &Option<String>,
+    deadline: &Option<chrono::NaiveDate>,
+) -> Result<uuid::Uuid, Error> {
+    // $1などはvaluesにしか使えないらしい
+    let insert = format!(
+        "insert into \"{}\" (id, task_name, description, member, deadline, status) \
+        values (uuid_generate_v4(), $1, $2, $3, $4, 1) returning id;",
+        channel_id
+    );
+
+    let rows = match client
+        .query(&insert, &[&task_name, description, member, deadline])
+        .await
+    {
+        Ok(rows) => rows,
+        Err(_e) => {
+            ensure_task_table(client, channel_id).await?;
+            client
+                .query(&insert, &[&task_name, description, member, deadline])
+                .await?
+        }
+    };
+    Ok(rows[0].get("id"))
+}
@@ -0,0 +1,223 @@
+//! タスクの依存関係の実装
+//!
+//! 「タスクBはタスクAが終わるまで始められない」を`kipp.dependencies`に
+//! (task_id = B, depends_on = A)として保存する.
+//! タスクIDはUUIDなので, 依存先は別チャンネルのタスクでもよい.
+
+use crate::db::{connect_to_db, find_task, locate_task, task_summary};
+use crate::support::{reply, short_id};
+use crate::workflow;
+use poise::serenity_prelude::{self as serenity, ChannelId, CreateMessage, Http};
+use tokio_postgres::Client;
+
+pub type Context<'a> = poise::Context<'a, super::Data, serenity::Error>;
+
+/// まだ完了していない依存先タスクを取得
+///
+/// (タスクID, タスク名)のリストを返す.
+pub async fn blockers(client: &Client, task_id: &uuid::Uuid) -> Vec<(uuid::Uuid, String)> {
+    let rows = match client
+        .query(
+            "select depends_on from kipp.dependencies where task_id=$1;",
+            &[task_id],
+        )
+        .await
+    {
+        Ok(rows) => rows,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut res = Vec::new();
+    for row in rows {
+        let dep: uuid::Uuid = row.get("depends_on");
+        let Some(channel) = locate_task(client, &dep).await else {
+            continue;
+        };
+        if let Some((name, status)) = task_summary(client, &channel, &dep).await {
+            if !workflow::is_done(status) {
+                res.push((dep, name));
+            }
+        }
+    }
+    res
+}
+
+/// `blocked by #1a2b3c4d タスク名`形式の文字列にする
+pub fn format_blockers(blockers: &[(uuid::Uuid, String)]) -> String {
+    blockers
+        .iter()
+        .map(|(id, name)| format!("blocked by #{} {}", short_id(id), name))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// 依存関係が循環するかどうか
+///
+/// `from`が`to`に依存しようとしたとき, `to`から辿って`from`に着くなら循環になる.
+async fn creates_cycle(client: &Client, from: &uuid::Uuid, to: &uuid::Uuid) -> bool {
+    if from == to {
+        return true;
+    }
+    let rows = client
+        .query(
+            "with recursive reach(id) as (
+                select depends_on from kipp.dependencies where task_id=$1
+                union
+                select d.depends_on from kipp.dependencies d join reach r on d.task_id = r.id
+            )
+            select count(*) from reach where id=$2;",
+            &[to, from],
+        )
+        .await;
+    match rows {
+        Ok(rows) => 0 < rows[0].get::<&str, i64>("count"),
+        // 判定できないときは安全側に倒す
+        Err(_) => true,
+    }
+}
+
+/// タスクの依存関係を追加
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `task_id` - 待たされる側のタスクID (UUIDv4)
+/// * `on` - 先に終わらせるタスクのID (UUIDv4). 他のチャンネルのタスクでもよい
+pub async fn depend(ctx: Context<'_>, task_id: String, on: String) -> Result<(), serenity::Error> {
+    let channel_id = ctx.channel_id().to_string();
+    let client = connect_to_db().await?;
+
+    let task_id = match find_task(&client, &channel_id, &task_id).await {
+        Some(id) => id,
+        None => {
+            reply(ctx, "このチャンネルにそのタスクはありません").await;
+            return Err(serenity::Error::Other("タスクが見つかりません"));
+        }
+    };
+    // 依存先はギルド内のどこにあってもよい
+    let on = match uuid::Uuid::parse_str(on.trim()) {
+        Ok(id) if locate_task(&client, &id).await.is_some() => id,
+        _ => {
+            reply(ctx, "依存先のタスクが見つかりません").await;
+            return Err(serenity::Error::Other("依存先タスクが見つかりません"));
+        }
+    };
+
+    // ---------- 循環チェック ----------
+    if creates_cycle(&client, &task_id, &on).await {
+        reply(ctx, "依存関係が循環するため追加できません").await;
+        return Err(serenity::Error::Other("依存関係が循環しています"));
+    }
+
+    let res = client
+        .execute(
+            "insert into kipp.dependencies (task_id, depends_on) values ($1, $2)
+            on conflict do nothing;",
+            &[&task_id, &on],
+        )
+        .await;
+    match res {
+        Ok(_) => reply(ctx, "依存関係を追加しました").await,
+        Err(_) => {
+            reply(ctx, "依存関係を追加できませんでした").await;
+            return Err(serenity::Error::Other("依存関係の追加に失敗しました"));
+        }
+    }
+    Ok(())
+}
+
+/// タスクの依存関係を削除
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `task_id` - 待たされる側のタスクID (UUIDv4)
+/// * `on` - 依存先のタスクID (UUIDv4)
+pub async fn undepend(
+    ctx: Context<'_>,
+    task_id: String,
+    on: String,
+) -> Result<(), serenity::Error> {
+    let channel_id = ctx.channel_id().to_string();
+    let client = connect_to_db().await?;
+
+    let task_id = match find_task(&client, &channel_id, &task_id).await {
+        Some(id) => id,
+        None => {
+            reply(ctx, "このチャンネルにそのタスクはありません").await;
+            return Err(serenity::Error::Other("タスクが見つかりません"));
+        }
+    };
+    let Ok(on) = uuid::Uuid::parse_str(on.trim()) else {
+        reply(ctx, "依存先のタスクIDが不正です").await;
+        return Err(serenity::Error::Other("依存先タスクIDが不正です"));
+    };
+
+    let res = client
+        .execute(
+            "delete from kipp.dependencies where task_id=$1 and depends_on=$2;",
+            &[&task_id, &on],
+        )
+        .await;
+    match res {
+        Ok(0) => reply(ctx, "その依存関係はありません").await,
+        Ok(_) => reply(ctx, "依存関係を削除しました").await,
+        Err(_) => {
+            reply(ctx, "依存関係を削除できませんでした").await;
+            return Err(serenity::Error::Other("依存関係の削除に失敗しました"));
+        }
+    }
+    Ok(())
+}
+
+/// タスクが完了したときに, それを待っていたタスクのチャンネルへお知らせする
+///
+/// 他にも未完了の依存先が残っているタスクはお知らせしない.
+pub async fn notify_unblocked(http: &Http, client: &Client, task_id: &uuid::Uuid) {
+    let rows = match client
+        .query(
+            "select task_id from kipp.dependencies where depends_on=$1;",
+            &[task_id],
+        )
+        .await
+    {
+        Ok(rows) => rows,
+        Err(_) => return,
+    };
+
+    // 完了したタスクの名前
+    let done_name = match locate_task(client, task_id).await {
+        Some(channel) => task_summary(client, &channel, task_id)
+            .await
+            .map(|(name, _)| name)
+            .unwrap_or_default(),
+        None => String::new(),
+    };
+
+    for row in rows {
+        let dependant: uuid::Uuid = row.get("task_id");
+        if !blockers(client, &dependant).await.is_empty() {
+            continue;
+        }
+        let Some(channel) = locate_task(client, &dependant).await else {
+            continue;
+        };
+        let Some((name, _)) = task_summary(client, &channel, &dependant).await else {
+            continue;
+        };
+        let Ok(channel_id) = channel.parse::<u64>() else {
+            continue;
+        };
+
+        let msg = format!(
+            "\u{1f513} 「{}」(#{}) が完了したので「{}」(#{}) を始められます",
+            done_name,
+            short_id(task_id),
+            name,
+            short_id(&dependant)
+        );
+        let _ = ChannelId::new(channel_id)
+            .send_message(http, CreateMessage::default().content(msg))
+            .await;
+    }
+}
@@ -2,139 +2,997 @@
 
 use chrono::*;
 use poise::serenity_prelude::{
-    self as serenity, ChannelId, CreateEmbed, CreateEmbedFooter, Error, UserId,
+    self as serenity, ButtonStyle, Channel, ChannelId, ChannelType, CreateActionRow, CreateButton,
+    CreateEmbed, CreateEmbedFooter, CreateInteractionResponse, EditInteractionResponse, Error,
+    GuildChannel, UserId,
 };
 use poise::CreateReply;
 use serenity::model::Timestamp;
+use std::collections::HashMap;
+use std::time::Duration;
 use uuid::{self};
 
-use crate::db::connect_to_db;
+use crate::action;
+use crate::checklist;
+use crate::comment;
+use crate::db::{connect_to_db, task_tables};
+use crate::dependency;
+use crate::priority::{self, Priority};
+use crate::sprint;
+use crate::support::short_id;
+use crate::tag;
+use crate::timetrack;
+use crate::workflow;
+use tokio_postgres::types::ToSql;
+use tokio_postgres::Client;
 
 /// 返信に使うコンテキスト
 pub type Context<'a> = poise::Context<'a, super::Data, Error>;
 
+/// 1ページに載せるEmbedの上限（Discordの上限）
+const PAGE_EMBEDS: usize = 10;
+
+/// 1ページに載せる文字数の上限（Discordの上限）
+const PAGE_CHARS: usize = 6000;
+
+/// ページ送りのボタンを押せる時間（秒）
+const PAGE_TIMEOUT: u64 = 180;
+
+/// `/show`の追加オプション
+///
+/// タグやステータスなど, 後から増えた絞り込みと並び順をまとめたもの.
+/// 指定したものはすべて満たす必要がある（and条件）.
+#[derive(Default)]
+pub struct ShowOptions {
+    /// タグ
+    pub tag: Option<String>,
+    /// `true`なら今のスプリントのタスク
+    pub sprint: Option<bool>,
+    /// ステータス（ワークフローの状態名）
+    pub status: Option<String>,
+    /// この日以前が〆切 (YYYY-MM-DD)
+    pub due_before: Option<String>,
+    /// この日以降が〆切 (YYYY-MM-DD)
+    pub due_after: Option<String>,
+    /// `true`なら〆切を過ぎた未完了のタスク
+    pub overdue: Option<bool>,
+    /// `true`なら担当者なしのタスク
+    pub unassigned: Option<bool>,
+    /// タスク名か概要に含む文字列
+    pub contains: Option<String>,
+    /// 優先度
+    pub priority: Option<Priority>,
+    /// 並べ替えの基準
+    pub sort: Option<Sort>,
+    /// 並べ替えの向き. 省略時は昇順
+    pub order: Option<Order>,
+    /// 表示形式. 省略時は詳細
+    pub view: Option<View>,
+}
+
+/// `/show`の表示形式
+#[derive(poise::ChoiceParameter, Clone, Copy, PartialEq)]
+pub enum View {
+    /// 1件ごとにEmbedを出す
+    #[name = "詳細"]
+    Full,
+    /// 1件1行で一覧にする
+    #[name = "コンパクト"]
+    Compact,
+}
+
+/// `/show`の並べ替えの基準
+#[derive(poise::ChoiceParameter, Clone, Copy)]
+pub enum Sort {
+    #[name = "〆切"]
+    Deadline,
+    #[name = "作成日"]
+    Created,
+    #[name = "優先度"]
+    Priority,
+    #[name = "名前"]
+    Name,
+}
+
+/// `/show`の並べ替えの向き
+#[derive(poise::ChoiceParameter, Clone, Copy)]
+pub enum Order {
+    #[name = "昇順"]
+    Asc,
+    #[name = "降順"]
+    Desc,
+}
+
+impl Sort {
+    /// `order by`に続く句
+    ///
+    /// 未設定の〆切や優先度は向きに関わらず最後に並べる.
+    fn order_by(self, order: Order) -> String {
+        let column = match self {
+            Sort::Deadline => "deadline",
+            Sort::Created => "created_at",
+            Sort::Priority => "priority",
+            Sort::Name => "task_name",
+        };
+        let dir = match order {
+            Order::Asc => "asc",
+            Order::Desc => "desc",
+        };
+        format!("{} {} nulls last", column, dir)
+    }
+}
+
+/// Embedを1ページに収まるように分ける
+///
+/// `embeds`は(Embed, 文字数)で渡す. 文字数はタイトル・説明・フィールド・フッターの合計.
+pub fn split_pages(embeds: Vec<(CreateEmbed, usize)>) -> Vec<Vec<CreateEmbed>> {
+    split_by(embeds, PAGE_EMBEDS)
+}
+
+/// (要素, 文字数)を1ページ`limit`件・`PAGE_CHARS`文字までに分ける
+fn split_by<T>(items: Vec<(T, usize)>, limit: usize) -> Vec<Vec<T>> {
+    let mut pages: Vec<Vec<T>> = Vec::new();
+    let mut page: Vec<T> = Vec::new();
+    let mut chars: usize = 0;
+    for (item, len) in items {
+        if limit <= page.len() || (!page.is_empty() && PAGE_CHARS < chars + len) {
+            pages.push(std::mem::take(&mut page));
+            chars = 0;
+        }
+        page.push(item);
+        chars += len;
+    }
+    if !page.is_empty() {
+        pages.push(page);
+    }
+    pages
+}
+
+/// ボタン付きのタスク表示で1ページに載せるEmbedの上限
+///
+/// ボタンの行は1通5行までなので, ページ送りの1行を残して4件にする.
+const ACTION_EMBEDS: usize = 4;
+
+/// 1ページ分の表示
+#[derive(Clone)]
+struct Page {
+    embeds: Vec<CreateEmbed>,
+    /// Embedの下に付けるボタンの行
+    rows: Vec<CreateActionRow>,
+}
+
+/// Embedの説明文の文字数の上限（Discordの上限）
+const DESCRIPTION_CHARS: usize = 4096;
+
+/// コンパクト表示で名前を切り詰める文字数
+const COMPACT_NAME_CHARS: usize = 40;
+
+/// コンパクト表示のステータスの絵文字
+fn status_emoji(
+    states: &[workflow::State],
+    status: i16,
+    deadline: Option<NaiveDate>,
+    today: NaiveDate,
+) -> &'static str {
+    match states.iter().find(|s| s.code == status) {
+        Some(state) if state.done => "\u{2705}",
+        Some(_) if deadline.is_some_and(|d| d < today) => "\u{1f534}",
+        Some(state) if state.code == 1 => "\u{26aa}",
+        Some(_) => "\u{1f535}",
+        None => "\u{2754}",
+    }
+}
+
+/// タスクを1件1行にしてページに分ける
+///
+/// 行は`#短いID 絵文字 名前 担当者 〆切`の形.
+fn compact_pages(
+    rows: &[tokio_postgres::Row],
+    states: &[workflow::State],
+) -> Vec<Vec<CreateEmbed>> {
+    let today = Local::now().date_naive();
+    let lines = rows.iter().map(|row| {
+        let task_id: uuid::Uuid = row.get("id");
+        let task_name: String = row.get("task_name");
+        let member: Option<String> = row.get("member");
+        let deadline: Option<NaiveDate> = row.get("deadline");
+        let status: i16 = row.get("status");
+        let emoji = status_emoji(states, status, deadline, today);
+
+        let mut name: String = task_name.chars().take(COMPACT_NAME_CHARS).collect();
+        if COMPACT_NAME_CHARS < task_name.chars().count() {
+            name.push('\u{2026}');
+        }
+        // メンションはEmbedの中なら通知されない
+        let member = member.map_or("-".to_string(), |m| format!("<@{}>", m));
+        let deadline = deadline.map_or("-".to_string(), |d| d.format("%m/%d").to_string());
+        format!(
+            "`#{}` {} {} \u{2014} {} \u{2014} {}",
+            short_id(&task_id),
+            emoji,
+            name,
+            member,
+            deadline
+        )
+    });
+    line_pages(&format!("タスク一覧 ({}件)", rows.len()), lines)
+}
+
+/// Embedのフィールドの文字数の上限（Discordの上限）
+const FIELD_CHARS: usize = 1024;
+
+/// 行をEmbedのフィールド1つに収まるように並べる
+///
+/// `limit`行か`FIELD_CHARS`文字に収まらない分は「ほかN件」にまとめる.
+pub fn field_lines(lines: &[String], limit: usize) -> String {
+    let mut shown: Vec<&str> = Vec::new();
+    let mut chars: usize = 0;
+    for line in lines.iter().take(limit) {
+        // 「ほかN件」の分を残しておく
+        if FIELD_CHARS - 16 < chars + line.chars().count() + 1 {
+            break;
+        }
+        chars += line.chars().count() + 1;
+        shown.push(line);
+    }
+    let mut field = shown.join("\n");
+    if shown.len() < lines.len() {
+        if !field.is_empty() {
+            field.push('\n');
+        }
+        field.push_str(&format!("ほか{}件", lines.len() - shown.len()));
+    }
+    field
+}
+
+/// 行を説明文に並べたEmbedをページに分ける
+///
+/// 説明文の上限で別のEmbedに分け, Embedは`split_pages`で1ページに収まるように分ける.
+/// Embedのタイトルはすべて`title`にする.
+pub fn line_pages(title: &str, lines: impl IntoIterator<Item = String>) -> Vec<Vec<CreateEmbed>> {
+    let mut embeds: Vec<(CreateEmbed, usize)> = Vec::new();
+    let mut description = String::new();
+    let mut push = |description: &mut String| {
+        let len = title.chars().count() + description.chars().count();
+        let embed = CreateEmbed::default()
+            .title(title)
+            .description(std::mem::take(description));
+        embeds.push((embed, len));
+    };
+    for line in lines {
+        if DESCRIPTION_CHARS < description.chars().count() + line.chars().count() + 1 {
+            push(&mut description);
+        }
+        description.push_str(&line);
+        description.push('\n');
+    }
+    if !description.is_empty() {
+        push(&mut description);
+    }
+    split_pages(embeds)
+}
+
+/// 前へ・次へのボタン
+///
+/// ボタンのIDにはコマンドのIDを付けて, 他の`/show`の返信と混ざらないようにする.
+fn page_buttons(ctx_id: u64, page: usize, pages: usize, disabled: bool) -> CreateActionRow {
+    CreateActionRow::Buttons(vec![
+        CreateButton::new(format!("{}prev", ctx_id))
+            .label("\u{25c0} 前へ")
+            .style(ButtonStyle::Secondary)
+            .disabled(disabled || page == 0),
+        CreateButton::new(format!("{}next", ctx_id))
+            .label("次へ \u{25b6}")
+            .style(ButtonStyle::Secondary)
+            .disabled(disabled || pages <= page + 1),
+    ])
+}
+
+/// ページの表示（`1 / 3 ページ`）
+fn page_indicator(page: usize, pages: usize) -> String {
+    format!("{} / {} ページ", page + 1, pages)
+}
+
+/// ページのボタンの行の後ろにページ送りのボタンを付ける
+fn page_components(
+    ctx_id: u64,
+    current: &Page,
+    page: usize,
+    pages: usize,
+    disabled: bool,
+) -> Vec<CreateActionRow> {
+    let mut rows = current.rows.clone();
+    rows.push(page_buttons(ctx_id, page, pages, disabled));
+    rows
+}
+
+/// ページを開くときに中身を用意するもの
+///
+/// 作るのに時間がかかるページは, 開かれたときに初めて作れるようにする.
+trait PageSource {
+    /// ページ数
+    fn count(&self) -> usize;
+
+    /// `page`ページ目の表示
+    async fn page(&mut self, ctx: Context<'_>, page: usize) -> Page;
+}
+
+impl PageSource for Vec<Page> {
+    fn count(&self) -> usize {
+        self.len()
+    }
+
+    async fn page(&mut self, _ctx: Context<'_>, page: usize) -> Page {
+        self[page].clone()
+    }
+}
+
+/// Embedのページを前へ・次へのボタン付きで返信する
+///
+/// 1ページしか無ければボタンは付けない.
+/// `PAGE_TIMEOUT`秒操作が無ければボタンを押せなくする.
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `pages` - ページごとのEmbed
+/// * `ephemeral` - 自分だけに見せる場合は`true`
+pub async fn paginate(ctx: Context<'_>, pages: Vec<Vec<CreateEmbed>>, ephemeral: bool) {
+    let pages: Vec<Page> = pages
+        .into_iter()
+        .map(|embeds| Page {
+            embeds,
+            rows: Vec::new(),
+        })
+        .collect();
+    paginate_pages(ctx, pages, ephemeral).await;
+}
+
+/// ページごとのボタンの行も付けて返信する
+///
+/// ページは開かれたときに`pages`から受け取る.
+/// ページ送りのボタンは時間切れで押せなくなるが, ページのボタンの行はそのまま残す.
+async fn paginate_pages(ctx: Context<'_>, mut pages: impl PageSource, ephemeral: bool) {
+    let ctx_id = ctx.id();
+    let count = pages.count();
+    if count == 0 {
+        return;
+    }
+    let mut page: usize = 0;
+    let mut current = pages.page(ctx, page).await;
+
+    let mut rep = CreateReply::default().ephemeral(ephemeral);
+    rep.embeds = current.embeds.clone();
+    if count == 1 {
+        let _ = ctx.send(rep.components(current.rows)).await;
+        return;
+    }
+    let rep = rep
+        .content(page_indicator(page, count))
+        .components(page_components(ctx_id, &current, page, count, false));
+    let Ok(handle) = ctx.send(rep).await else {
+        return;
+    };
+    let Ok(msg) = handle.message().await else {
+        return;
+    };
+
+    // ---------- ボタンが押されるたびにページを差し替える ----------
+    while let Some(mi) = msg
+        .await_component_interaction(ctx)
+        .custom_ids(vec![format!("{}prev", ctx_id), format!("{}next", ctx_id)])
+        .timeout(Duration::from_secs(PAGE_TIMEOUT))
+        .await
+    {
+        // ページを作っている間に応答の期限が切れないように先に受け付けておく
+        let _ = mi
+            .create_response(ctx, CreateInteractionResponse::Acknowledge)
+            .await;
+        if mi.data.custom_id.ends_with("next") {
+            page = (page + 1).min(count - 1);
+        } else {
+            page = page.saturating_sub(1);
+        }
+        current = pages.page(ctx, page).await;
+        let _ = mi
+            .edit_response(
+                ctx,
+                EditInteractionResponse::new()
+                    .content(page_indicator(page, count))
+                    .embeds(current.embeds.clone())
+                    .components(page_components(ctx_id, &current, page, count, false)),
+            )
+            .await;
+    }
+
+    // ---------- 時間切れ ----------
+    let mut rep = CreateReply::default()
+        .content(page_indicator(page, count))
+        .components(page_components(ctx_id, &current, page, count, true));
+    rep.embeds = current.embeds;
+    let _ = handle.edit(ctx, rep).await;
+}
+
 /*
     TODO: showall, showのリファクタ
     - ドキュメンテーションコメントのために分割したモジュールを再統合
 */
 
+/// 実行したユーザーが見られるこのサーバーのタスクテーブル（チャンネル）
+///
+/// スレッドは親チャンネルの権限で判断し, プライベートスレッドは参加しているか
+/// スレッドの管理権限があるときだけ見られるものとする.
+pub async fn visible_channels(ctx: Context<'_>, client: &Client) -> Vec<GuildChannel> {
+    let (Some(guild), Some(member)) = (ctx.partial_guild().await, ctx.author_member().await) else {
+        return Vec::new();
+    };
+    let tables = task_tables(client).await.unwrap_or_default();
+
+    let mut res: Vec<GuildChannel> = Vec::new();
+    for table in tables {
+        let Ok(id) = table.parse::<u64>() else {
+            continue;
+        };
+        let Ok(Channel::Guild(ch)) = ChannelId::new(id).to_channel(ctx).await else {
+            continue;
+        };
+        if ch.guild_id != guild.id {
+            continue;
+        }
+        // ---------- 権限の確認 ----------
+        let perms = match (ch.thread_metadata.is_some(), ch.parent_id) {
+            (true, Some(parent)) => match parent.to_channel(ctx).await {
+                Ok(Channel::Guild(parent)) => guild.user_permissions_in(&parent, &member),
+                _ => continue,
+            },
+            _ => guild.user_permissions_in(&ch, &member),
+        };
+        if !perms.view_channel() {
+            continue;
+        }
+        if ch.kind == ChannelType::PrivateThread
+            && !perms.manage_threads()
+            && ch
+                .id
+                .get_thread_member(ctx, member.user.id, false)
+                .await
+                .is_err()
+        {
+            continue;
+        }
+        res.push(ch);
+    }
+    res
+}
+
+/// `/showall`で数えたチャンネル1つ分
+struct ChannelCount {
+    channel_id: ChannelId,
+    /// 未完了のタスクの数
+    open: i64,
+    /// 〆切を過ぎた未完了のタスクの数
+    overdue: i64,
+    /// (状態名, 件数). ワークフローの順
+    counts: Vec<(String, i64)>,
+}
+
+/// チャンネルごとにタスクの数をステータス別に一覧表示
+///
+/// 未完了のタスクが多い順に並べ, 最後にサーバー全体の合計を付ける.
+/// 実行したユーザーが見られないチャンネルは出さない.
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `user` - ユーザーを限定して数える場合は入力
+/// * `display` - 自分以外にも表示させる場合は`true`
+/// * `tag` - タグを限定して数える場合は入力
 pub async fn showall(
     ctx: Context<'_>,
     user: Option<serenity::User>,
     display: Option<bool>,
+    tag: Option<String>,
 ) -> Result<(), Error> {
-    // ---------- コマンドを実行したチャンネルID ----------
-    let _this_channel_id = ctx.channel_id().to_string();
-
     // ---------- 共通処理 ----------
     // DBへの接続を試行
-    let client = connect_to_db().await.unwrap();
+    let client = connect_to_db().await?;
+    let guild_id = ctx.guild_id().map(|id| id.to_string());
+
+    // ---------- 絞り込み条件 ----------
+    let usr_id: Option<String> = user.map(|usr| usr.id.to_string());
+    let mut conds: Vec<String> = Vec::new();
+    let mut params: Vec<&(dyn ToSql + Sync)> = Vec::new();
+    if let Some(usr_id) = &usr_id {
+        params.push(usr_id);
+        conds.push(format!("member=${}", params.len()));
+    }
+    if let Some(tag) = &tag {
+        params.push(tag);
+        conds.push(format!(
+            "id in (select task_id from kipp.task_tags where tag=${})",
+            params.len()
+        ));
+    }
+    let where_clause = if conds.is_empty() {
+        String::new()
+    } else {
+        format!(" where {}", conds.join(" and "))
+    };
+
+    // ---------- チャンネルごとに数える ----------
+    let mut channels: Vec<ChannelCount> = Vec::new();
+    // サーバー全体の状態名ごとの件数（ワークフローの順）
+    let mut totals: Vec<(String, i64)> = Vec::new();
+    for ch in visible_channels(ctx, &client).await {
+        let channel_id = ch.id.to_string();
+        let query = format!(
+            "select status, count(*) as count,
+                count(*) filter (where status > 0 and deadline < current_date) as overdue
+            from \"{}\"{} group by status;",
+            channel_id, where_clause
+        );
+        let Ok(rows) = client.query(&query, &params).await else {
+            continue;
+        };
+        if rows.is_empty() {
+            continue;
+        }
 
-    // ---------- ギルド内のテキストチャンネル及びフォーラムチャンネルの取得 ----------
-    // DB内のすべてのテーブル名を取得 "{}"はあとで除く
-    let all_tables_query = "select tablename from pg_tables
-    where schemaname not in('pg_catalog','information_schema')
-    order by tablename;"
-        .to_string();
-
-    // クエリ投げ
-    let tables = client.query(&all_tables_query, &[]).await;
-
-    // 返信用
-    let mut rep_string: String = String::new();
-    // 検索用クエリ
-    let mut queries: Vec<String> = Vec::new();
-    let mut channels_id: Vec<String> = Vec::new();
-
-    let _res = match tables {
-        // ---------- テーブルが帰ってきた場合 ----------
-        Ok(tables) => {
-            // ---------- ユーザー選択あり ----------
-            if let Some(usr) = user {
-                // ========= ユーザー選択あり =========
-                let usr_id = usr.id.to_string();
-
-                for table in tables {
-                    // チャンネルID
-                    let channel_id: String = table.get("tablename");
-                    // {}が帰ってきたらとばす
-                    if &channel_id == "{}" {
-                        continue;
-                    }
-
-                    // 検索クエリ
-                    queries.push(format!(
-                        "select count(*) from \"{}\" where member=\'{}\'",
-                        channel_id, usr_id
-                    ));
-
-                    channels_id.push(channel_id);
-                }
+        let states = workflow::states(&client, &channel_id, guild_id.as_deref()).await;
+        let mut open: i64 = 0;
+        let mut overdue: i64 = 0;
+        let mut counts: Vec<(String, i64)> = Vec::new();
+        for state in &states {
+            let count: i64 = rows
+                .iter()
+                .filter(|row| row.get::<&str, i16>("status") == state.code)
+                .map(|row| row.get::<&str, i64>("count"))
+                .sum();
+            if count == 0 {
+                continue;
             }
-            // ---------- ユーザー選択なし ----------
-            else {
-                // ========= ユーザー選択なし =========
-                for table in tables {
-                    // チャンネルID
-                    let channel_id: String = table.get("tablename");
-
-                    // {}が帰ってきたらとばす
-                    if &channel_id == "{}" {
-                        continue;
-                    }
-                    // 検索クエリ
-                    queries.push(format!("select count(*) from \"{}\";", channel_id));
-
-                    channels_id.push(channel_id);
-                }
+            counts.push((state.name.clone(), count));
+        }
+        // ワークフローに無い状態は「その他」にまとめる
+        let other: i64 = rows
+            .iter()
+            .filter(|row| {
+                let status: i16 = row.get("status");
+                !states.iter().any(|s| s.code == status)
+            })
+            .map(|row| row.get::<&str, i64>("count"))
+            .sum();
+        if 0 < other {
+            counts.push(("その他".to_string(), other));
+        }
+        for row in &rows {
+            let status: i16 = row.get("status");
+            if !workflow::is_done(status) {
+                open += row.get::<&str, i64>("count");
             }
+            overdue += row.get::<&str, i64>("overdue");
+        }
 
-            // クエリ個数でループさせる
-            let size = queries.len();
-            for i in 0..size {
-                // クエリ送信
-                let count = client.query(&queries[i], &[]).await.unwrap();
-                // チャンネル内のタスクを数える
-                let count: i64 = count[0].get("count");
-
-                // --------- 返信 ---------
-                let channel_id = ChannelId::new(channels_id[i].parse::<u64>().unwrap());
-                match channel_id.to_channel(ctx.http()).await {
-                    Ok(ch) => {
-                        let s = format!("| {} | : {} 件\n", ch, count);
-                        rep_string.push_str(&s);
-                    }
-                    Err(_) => {
-                        let s = format!("| 不明なチャンネル | {}件\n", count);
-                        rep_string.push_str(&s);
-                    }
-                };
+        for (name, count) in &counts {
+            match totals.iter_mut().find(|(n, _)| n == name) {
+                Some((_, total)) => *total += count,
+                None => totals.push((name.clone(), *count)),
             }
+        }
+        channels.push(ChannelCount {
+            channel_id: ch.id,
+            open,
+            overdue,
+            counts,
+        });
+    }
+
+    // ---------- 返信を見せるかどうか ----------
+    let is_disp = if let Some(b) = display { !b } else { true };
+    if channels.is_empty() {
+        let rep = CreateReply::default()
+            .content("タスクはありません")
+            .ephemeral(is_disp);
+        let _ = ctx.send(rep).await;
+        return Ok(());
+    }
+
+    // ---------- 未完了の多い順に並べる ----------
+    channels.sort_by(|a, b| b.open.cmp(&a.open).then(b.overdue.cmp(&a.overdue)));
+    let format_counts = |counts: &[(String, i64)]| {
+        counts
+            .iter()
+            .map(|(name, count)| format!("{} {}", name, count))
+            .collect::<Vec<String>>()
+            .join(" / ")
+    };
+    let format_overdue = |overdue: i64| {
+        if 0 < overdue {
+            format!("  \u{26a0}\u{fe0f} 期限切れ {}", overdue)
+        } else {
+            String::new()
+        }
+    };
+    let total_open: i64 = channels.iter().map(|c| c.open).sum();
+    let total_overdue: i64 = channels.iter().map(|c| c.overdue).sum();
+
+    let mut lines: Vec<String> = channels
+        .iter()
+        .map(|c| {
+            format!(
+                "<#{}> : {}{}",
+                c.channel_id,
+                format_counts(&c.counts),
+                format_overdue(c.overdue)
+            )
+        })
+        .collect();
+    lines.push(format!(
+        "**合計** : {}{}",
+        format_counts(&totals),
+        format_overdue(total_overdue)
+    ));
 
-            // // ---------- 返信を見せるかどうか ----------
-            let is_disp = if let Some(b) = display { !b } else { true };
-            // ---------- リプライビルダー作成 ----------
-            if rep_string != "" {
-                let rep = CreateReply::default()
-                    .content(rep_string)
-                    .ephemeral(is_disp);
-                let _ = ctx.send(rep).await;
+    let title = format!("タスク数（未完了 {} 件）", total_open);
+    paginate(ctx, line_pages(&title, lines), is_disp).await;
+    Ok(())
+}
+
+/// `/mytasks`に並べるタスク1件
+struct AssignedTask {
+    task_id: uuid::Uuid,
+    task_name: String,
+    deadline: Option<NaiveDate>,
+    /// 状態名
+    status: String,
+}
+
+/// ユーザーが担当している未完了のタスクをサーバー全体から一覧表示
+///
+/// チャンネルごとにまとめ, 〆切の近い順に並べる. 〆切を過ぎたものは強調する.
+/// 実行したユーザーが見られないチャンネルのタスクは出さない.
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `user` - 担当者. 省略時は自分
+/// * `display` - 自分以外にも表示させる場合は`true`
+pub async fn mytasks(
+    ctx: Context<'_>,
+    user: Option<serenity::User>,
+    display: Option<bool>,
+) -> Result<(), Error> {
+    let user = user.unwrap_or_else(|| ctx.author().clone());
+    let usr_id = user.id.to_string();
+    let client = connect_to_db().await?;
+    let guild_id = ctx.guild_id().map(|id| id.to_string());
+    let today = Local::now().date_naive();
+
+    // ---------- チャンネルごとに集める ----------
+    let mut groups: Vec<(ChannelId, Vec<AssignedTask>)> = Vec::new();
+    for ch in visible_channels(ctx, &client).await {
+        let channel_id = ch.id.to_string();
+        let query = format!(
+            "select id, task_name, deadline, status from \"{}\"
+            where member=$1 and status > 0 order by deadline nulls last;",
+            channel_id
+        );
+        let Ok(rows) = client.query(&query, &[&usr_id]).await else {
+            continue;
+        };
+        if rows.is_empty() {
+            continue;
+        }
+        let states = workflow::states(&client, &channel_id, guild_id.as_deref()).await;
+        let tasks = rows
+            .iter()
+            .map(|row| AssignedTask {
+                task_id: row.get("id"),
+                task_name: row.get("task_name"),
+                deadline: row.get("deadline"),
+                status: workflow::name_of(&states, row.get("status")),
+            })
+            .collect();
+        groups.push((ch.id, tasks));
+    }
+
+    // ---------- 返信を見せるかどうか ----------
+    let is_disp = if let Some(b) = display { !b } else { true };
+    if groups.is_empty() {
+        let rep = CreateReply::default()
+            .content(format!(
+                "{} が担当している未完了のタスクはありません",
+                user.name
+            ))
+            .ephemeral(is_disp);
+        let _ = ctx.send(rep).await;
+        return Ok(());
+    }
+
+    // ---------- 〆切の近いタスクがあるチャンネルから並べる ----------
+    groups.sort_by_key(|(_, tasks)| {
+        let first = tasks.first().and_then(|t| t.deadline);
+        (first.is_none(), first)
+    });
+    let total: usize = groups.iter().map(|(_, tasks)| tasks.len()).sum();
+    let mut lines: Vec<String> = Vec::new();
+    for (channel_id, tasks) in &groups {
+        // チャンネルのメンションはそのままジャンプリンクになる
+        lines.push(format!("**<#{}>**", channel_id));
+        for AssignedTask {
+            task_id,
+            task_name,
+            deadline,
+            status,
+        } in tasks
+        {
+            let line = match deadline {
+                Some(d) if *d < today => format!(
+                    "\u{1f534} `#{}` **{}** \u{2014} {} \u{2014} **{}（超過）**",
+                    short_id(task_id),
+                    task_name,
+                    status,
+                    d.format("%Y-%m-%d")
+                ),
+                Some(d) => format!(
+                    "`#{}` {} \u{2014} {} \u{2014} {}",
+                    short_id(task_id),
+                    task_name,
+                    status,
+                    d.format("%Y-%m-%d")
+                ),
+                None => format!("`#{}` {} \u{2014} {}", short_id(task_id), task_name, status),
+            };
+            lines.push(line);
+        }
+    }
+
+    let title = format!("{} の未完了のタスク（{} 件）", user.name, total);
+    paginate(ctx, line_pages(&title, lines), is_disp).await;
+    Ok(())
+}
+
+/// タスクのEmbedのフッター
+const TASK_FOOTER: &str = "コマンド";
+
+/// `/show`の詳細表示のページ
+///
+/// タスクのEmbedは, そのページが開かれたときに初めて作る.
+struct TaskPages {
+    client: Client,
+    /// チャンネルのワークフロー
+    states: Vec<workflow::State>,
+    /// ページごとのタスク
+    chunks: Vec<Vec<tokio_postgres::Row>>,
+    /// 作ったページ. 開いたことのあるページだけ入っている
+    rendered: Vec<Option<Page>>,
+    /// 担当者のIDと表示名. 同じユーザーを何度も引かないように覚えておく
+    users: HashMap<String, String>,
+}
+
+impl PageSource for TaskPages {
+    fn count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    async fn page(&mut self, ctx: Context<'_>, page: usize) -> Page {
+        if let Some(Some(rendered)) = self.rendered.get(page) {
+            return rendered.clone();
+        }
+        let mut cards = Vec::new();
+        for row in &self.chunks[page] {
+            cards.push(task_card(ctx, &self.client, &self.states, &mut self.users, row).await);
+        }
+        fit_descriptions(&mut cards);
+        let (embeds, rows) = cards.into_iter().map(TaskCard::build).unzip();
+        let res = Page { embeds, rows };
+
+        if self.rendered.len() <= page {
+            self.rendered.resize(page + 1, None);
+        }
+        self.rendered[page] = Some(res.clone());
+        res
+    }
+}
+
+/// タスク1件分のEmbedの中身
+struct TaskCard {
+    title: String,
+    description: String,
+    color: i32,
+    fields: Vec<(&'static str, String, bool)>,
+    /// Embedの下に付けるボタンの行
+    buttons: CreateActionRow,
+}
+
+impl TaskCard {
+    /// 概要以外の文字数
+    fn fixed_len(&self) -> usize {
+        self.title.chars().count()
+            + TASK_FOOTER.chars().count()
+            + self
+                .fields
+                .iter()
+                .map(|(name, value, _)| name.chars().count() + value.chars().count())
+                .sum::<usize>()
+    }
+
+    fn build(self) -> (CreateEmbed, CreateActionRow) {
+        let embed = CreateEmbed::default()
+            .title(self.title)
+            .description(self.description)
+            .color(self.color)
+            .fields(self.fields)
+            .footer(CreateEmbedFooter::new(TASK_FOOTER))
+            .timestamp(Timestamp::now());
+        (embed, self.buttons)
+    }
+}
+
+/// 1ページが`PAGE_CHARS`文字を超えるなら, 超えない長さまで概要を切り詰める
+fn fit_descriptions(cards: &mut [TaskCard]) {
+    let fixed: usize = cards.iter().map(TaskCard::fixed_len).sum();
+    let total: usize = fixed
+        + cards
+            .iter()
+            .map(|c| c.description.chars().count())
+            .sum::<usize>();
+    if total <= PAGE_CHARS || cards.is_empty() {
+        return;
+    }
+    // 省略記号の分を残して等分する
+    let share = (PAGE_CHARS.saturating_sub(fixed) / cards.len()).max(1);
+    for card in cards.iter_mut() {
+        if share < card.description.chars().count() {
+            let mut short: String = card.description.chars().take(share - 1).collect();
+            short.push('\u{2026}');
+            card.description = short;
+        }
+    }
+}
+
+/// タスクの行からEmbedの中身を作る
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `client` - DBのクライアント
+/// * `states` - チャンネルのワークフロー
+/// * `users` - 引いたことのある担当者の表示名
+/// * `row` - タスクの行
+async fn task_card(
+    ctx: Context<'_>,
+    client: &Client,
+    states: &[workflow::State],
+    users: &mut HashMap<String, String>,
+    row: &tokio_postgres::Row,
+) -> TaskCard {
+    // ---------- まずはrowから情報を抜き出す ----------
+    let task_uuid: uuid::Uuid = row.get("id");
+    let task_id = task_uuid.to_string(); // タスクID
+    let task_name: String = row.get("task_name"); // タスク名
+    let description: Option<String> = row.get("description"); // 概要
+    let member: Option<String> = row.get("member"); // 担当者
+    let deadline: Option<chrono::NaiveDate> = row.get("deadline"); // 〆切日
+    let status: i16 = row.get("status"); // ステータス
+    let estimate: Option<i32> = row.get("estimate"); // 見積もり（分）
+    let priority: Option<i16> = row.get("priority"); // 優先度
+
+    // ---------- ボタンの状態 ----------
+    let started = action::start_state(states) == Some(status);
+    let done = workflow::is_done(status);
+
+    // NOTE: 色と名前はワークフローの設定から
+    let (status, color) = match states.iter().find(|s| s.code == status) {
+        // NOTE: 着手済み（未着手と完了扱い以外）でも日付が過ぎていたら赤色
+        // 現在時刻を取得
+        Some(state) if !state.done && state.code != 1 => {
+            let now_dt: DateTime<Local> = Local::now();
+
+            // 比較
+            let naive_now_dt = now_dt.naive_local().date(); // 現在の日付
+
+            // 締切日が設定されていない or 締切がまだ
+            if deadline.is_none() || deadline.unwrap() > naive_now_dt {
+                (state.name.clone(), state.color.unwrap_or(0))
             }
-            // ギルド内のタスクが0件の場合
+            // 締め切り過ぎてる
             else {
-                let rep = CreateReply::default()
-                    .content("タスクはありません")
-                    .ephemeral(is_disp);
-                let _ = ctx.send(rep).await;
+                (format!("{}（超過）", state.name), 0xff0000)
             }
         }
-        // ---------- テーブルが帰ってこなかった場合（多分無い） ----------
-        Err(_) => {
-            return Err(Error::Other("Cannot find tasks.!".into()));
-        }
+        Some(state) => (state.name.clone(), state.color.unwrap_or(0)),
+
+        // 1 => ("進行中", (0, 255, 0)),
+        None => ("その他".to_string(), 0xff0000),
     };
 
-    Ok(())
+    // ---------- descriptionがNoneなら無にする ----------
+    let con_description = description.unwrap_or_else(|| "説明なし".to_string());
+
+    // ---------- memberがNoneかどうか ----------
+    // UserIdからUserNameを探す. 一度引いたユーザーは覚えたものを使う
+    let content_user_name = match member {
+        Some(m) => match users.get(&m) {
+            Some(name) => name.clone(),
+            None => {
+                let usr_name = match m.parse::<u64>() {
+                    Ok(id) => match UserId::new(id).to_user(ctx).await {
+                        Ok(usr) => usr.to_string(),
+                        Err(_) => "不明なユーザー".to_string(),
+                    },
+                    Err(_) => "不明なユーザー".to_string(),
+                };
+                users.insert(m, usr_name.clone());
+                usr_name
+            }
+        },
+        None => "None".to_string(),
+    };
+    // ---------- 締切日が設定されているかどうか ----------
+    let dline = if let Some(d) = deadline {
+        d.format("%Y-%m-%d").to_string()
+    } else {
+        "〆切はありません".to_string()
+    };
+
+    // ---------- タグ ----------
+    let tags = tag::task_tags(client, &task_uuid).await;
+    let tags = if tags.is_empty() {
+        "なし".to_string()
+    } else {
+        tags.iter()
+            .map(|t| format!("`{}`", t))
+            .collect::<Vec<String>>()
+            .join(" ")
+    };
+
+    let mut fields = vec![
+        ("タスクID", task_id, false),
+        ("担当者", content_user_name, true),
+        ("〆切", dline, true),
+        ("ステータス", status, true),
+        ("タグ", tags, false),
+    ];
+
+    // ---------- 優先度 ----------
+    if let Some(p) = priority {
+        fields.push(("優先度", priority::name_of(p).to_string(), true));
+    }
+
+    // ---------- チェックリストの進捗 ----------
+    if let Some((done, total)) = checklist::progress(client, &task_uuid).await {
+        fields.push((
+            "チェックリスト",
+            checklist::format_progress(done, total),
+            true,
+        ));
+    }
+
+    // ---------- 工数 ----------
+    if let Some(time) = timetrack::summary(client, &task_uuid, estimate).await {
+        fields.push(("工数", time, true));
+    }
+
+    // ---------- コメント数 ----------
+    let comments = comment::count(client, &task_uuid).await;
+    if 0 < comments {
+        fields.push(("コメント", format!("{} 件", comments), true));
+    }
+
+    // ---------- 依存先の未完了タスク ----------
+    let blockers = dependency::blockers(client, &task_uuid).await;
+    if !blockers.is_empty() {
+        fields.push(("ブロック", dependency::format_blockers(&blockers), false));
+    }
+
+    TaskCard {
+        title: task_name,
+        description: con_description,
+        color,
+        fields,
+        buttons: action::buttons(&task_uuid, started, done),
+    }
 }
 
 /// チャンネルに属すタスクを表示
@@ -150,150 +1008,209 @@ pub async fn showall(
 ///
 /// * `ctx` - コマンド起動時の情報が入ったブツ
 /// * `user` - ユーザーを限定して出力させる場合は入力
-/// * `is_done_print` - 完了済みのタスクを表示させる default: false
+/// * `is_done_print` - 完了済みのタスクを非表示にさせる default: true
 /// * `display` - 自分だけのメッセージとして表示させる場合は`true`
+/// * `opts` - タグやステータスなどの絞り込みと並び順
 pub async fn show(
     ctx: Context<'_>,
     user: Option<serenity::User>,
     is_done_print: Option<bool>,
     display: Option<bool>,
+    opts: ShowOptions,
 ) -> Result<(), Error> {
+    let ShowOptions {
+        tag,
+        sprint,
+        status,
+        due_before,
+        due_after,
+        overdue,
+        unassigned,
+        contains,
+        priority,
+        sort,
+        order,
+        view,
+    } = opts;
+
     // コマンドを実行したチャンネルID
     let this_channel_id = ctx.channel_id();
+    let channel_str = this_channel_id.to_string();
 
     // ---------- 共通処理 ----------
     // DBへの接続を試行
     let client = connect_to_db().await.unwrap();
 
-    // テーブル取得
-    let q: String;
-    match user {
-        // ---------- ユーザー選択あり->指定ユーザーのタスク ----------
-        Some(usr) => {
-            let usr_id = usr.id.to_string();
-            q = format!(
-                "select * from \"{}\" where member=\'{}\'",
-                this_channel_id, usr_id
-            );
-        }
-        // ---------- ユーザー選択なし->全ユーザーのタスク ----------
-        None => {
-            q = format!("select * from \"{}\"", this_channel_id);
-        }
-    }
-
     // ---------- 返信を見せるかどうか ----------
     // 原則は自分のみ表示
     let is_disp = if let Some(b) = display { !b } else { true };
-    let is_done_print = if let Some(b) = is_done_print {
-        !b
-    } else {
-        // 既定値はfalse
-        false
-    };
+    // 完了済みは原則非表示
+    let hide_done = is_done_print.unwrap_or(true);
 
-    let rows = client.query(&q, &[]).await;
-    match rows {
-        Ok(rows) => {
-            /* Embed作成 */
-
-            // rows<vec>の中身が空でない場合
-            if !rows.is_empty() {
-                let mut task_embeds = Vec::new();
-                for row in rows {
-                    // ---------- まずはrowから情報を抜き出す ----------
-                    let task_id = row.get::<&str, uuid::Uuid>("id").to_string(); // タスクID
-                    let task_name: String = row.get("task_name"); // タスク名
-                    let description: Option<String> = row.get("description"); // 概要
-                    let member: Option<String> = row.get("member"); // 担当者
-                    let deadline: Option<chrono::NaiveDate> = row.get("deadline"); // 〆切日
-                    let status: i16 = row.get("status"); // ステータス
-
-                    // TODO: 完了済みタスクを非表示にさせる実装
-                    // continueで回す
-                    let (status, color) = match status {
-                        0 => ("完了済み", (0, 0, 0)),
-                        1 => ("未着手", (0, 0, 0)),
-                        // NOTE: 進行中でも日付が過ぎていたら赤色
-                        // 現在時刻を取得
-                        2 => {
-                            let now_dt: DateTime<Local> = Local::now();
-
-                            // 比較
-                            let naive_now_dt = now_dt.naive_local().date(); // 現在の日付
-
-                            // 締切日が設定されていない or 締切がまだ
-                            if deadline == None || deadline.unwrap() > naive_now_dt {
-                                ("進行中", (0, 255, 0))
-                            }
-                            // 締め切り過ぎてる
-                            else {
-                                ("進行中（超過）", (255, 0, 0))
-                            }
-                        }
-
-                        // 1 => ("進行中", (0, 255, 0)),
-                        _ => ("その他", (255, 0, 0)),
-                    };
-
-                    // ---------- descriptionがNoneなら無にする ----------
-                    let con_description = description.unwrap_or_else(|| "説明なし".to_string());
-
-                    // UserIDに変換
-                    // 最終的にembedへ組み込む
-                    let content_user_name;
-                    // ---------- memberがNoneかどうか ----------
-                    if let Some(m) = member {
-                        let member_int = m.parse::<u64>().unwrap();
-                        let usr_id = UserId::new(member_int);
-                        // UserIdからUserNameを探す
-                        let usr_name = usr_id.to_user(ctx).await;
-
-                        let usr_name = match usr_name {
-                            Ok(usr) => usr.to_string(),
-                            Err(_) => "不明なユーザー".to_string(),
-                        };
-                        content_user_name = usr_name;
-                    } else {
-                        content_user_name = "None".to_string();
-                    };
-                    // ---------- 締切日が設定されているかどうか ----------
-                    let dline = if let Some(d) = deadline {
-                        d.format("%Y-%m-%d").to_string()
-                    } else {
-                        "〆切はありません".to_string()
-                    };
-
-                    // TODO: 完了済みを表示させなくする
-                    let embed = CreateEmbed::default()
-                        .title(task_name)
-                        .description(format!("{}", con_description))
-                        .color(color)
-                        .fields(vec![
-                            ("タスクID", task_id, false),
-                            ("担当者", content_user_name, true),
-                            ("〆切", dline, true),
-                            ("ステータス", status.to_string(), true),
-                        ])
-                        .footer(CreateEmbedFooter::new("コマンド"))
-                        .timestamp(Timestamp::now());
-
-                    task_embeds.push(embed);
-                }
-                let mut rep_builder = CreateReply::default().ephemeral(is_disp);
-                rep_builder.embeds = task_embeds;
+    // ---------- チャンネルのワークフロー ----------
+    let guild_id = ctx.guild_id().map(|id| id.to_string());
+    let states = workflow::states(&client, &channel_str, guild_id.as_deref()).await;
+
+    // ---------- ステータス名から番号へ ----------
+    let status_code: Option<i16> = match &status {
+        Some(name) => match states.iter().find(|s| s.name == name.trim()) {
+            Some(state) => Some(state.code),
+            None => {
+                let rep_builder = CreateReply::default()
+                    .ephemeral(true)
+                    .content("そのステータスはこのチャンネルのワークフローにありません");
                 let _ = ctx.send(rep_builder).await;
+                return Err(Error::Other("ステータスが見つかりません"));
             }
-            // rows<vec>の中身が空の場合
-            else {
+        },
+        None => None,
+    };
+
+    // ---------- 〆切の範囲 ----------
+    let parse_date = |d: &Option<String>| match d {
+        Some(d) => NaiveDate::parse_from_str(d.trim(), "%Y-%m-%d")
+            .ok()
+            .map(Some),
+        None => Some(None),
+    };
+    let (Some(due_before), Some(due_after)) = (parse_date(&due_before), parse_date(&due_after))
+    else {
+        let rep_builder = CreateReply::default()
+            .ephemeral(true)
+            .content("日付は YYYY-MM-DD の形式で入力してください");
+        let _ = ctx.send(rep_builder).await;
+        return Err(Error::Other("日付の形式が不正です"));
+    };
+    let priority: Option<i16> = priority.map(Priority::code);
+
+    // ---------- 絞り込み条件 ----------
+    let usr_id: Option<String> = user.map(|usr| usr.id.to_string());
+    let mut conds: Vec<String> = Vec::new();
+    let mut params: Vec<&(dyn ToSql + Sync)> = Vec::new();
+    // ユーザー選択あり->指定ユーザーのタスク
+    if let Some(usr_id) = &usr_id {
+        params.push(usr_id);
+        conds.push(format!("member=${}", params.len()));
+    }
+    // タグ選択あり->タグの付いたタスク
+    if let Some(tag) = &tag {
+        params.push(tag);
+        conds.push(format!(
+            "id in (select task_id from kipp.task_tags where tag=${})",
+            params.len()
+        ));
+    }
+    // スプリント指定あり->今のスプリントのタスク
+    if sprint == Some(true) {
+        params.push(&channel_str);
+        conds.push(sprint::active_condition(params.len()));
+    }
+    // ステータス指定あり->そのステータスのタスク（完了扱いの状態でも表示）
+    // 指定なし->非表示にするなら完了扱いの状態を除く
+    if let Some(code) = &status_code {
+        params.push(code);
+        conds.push(format!("status=${}", params.len()));
+    } else if hide_done {
+        conds.push("status > 0".to_string());
+    }
+    // 〆切の範囲（両端を含む）
+    if let Some(d) = &due_before {
+        params.push(d);
+        conds.push(format!("deadline <= ${}", params.len()));
+    }
+    if let Some(d) = &due_after {
+        params.push(d);
+        conds.push(format!("deadline >= ${}", params.len()));
+    }
+    // 〆切を過ぎた未完了のタスク
+    if overdue == Some(true) {
+        conds.push("deadline < current_date and status > 0".to_string());
+    }
+    // 担当者なし
+    if unassigned == Some(true) {
+        conds.push("member is null".to_string());
+    }
+    // タスク名か概要に含む（大文字小文字は区別しない）
+    if let Some(text) = &contains {
+        params.push(text);
+        conds.push(format!(
+            "(strpos(lower(task_name), lower(${0})) > 0 \
+            or strpos(lower(coalesce(description, '')), lower(${0})) > 0)",
+            params.len()
+        ));
+    }
+    if let Some(p) = &priority {
+        params.push(p);
+        conds.push(format!("priority=${}", params.len()));
+    }
+
+    // テーブル取得
+    let mut q = format!("select * from \"{}\"", this_channel_id);
+    if !conds.is_empty() {
+        q.push_str(&format!(" where {}", conds.join(" and ")));
+    }
+    // 並び順
+    // 向きだけ指定された場合は〆切で並べる
+    if let Some(sort) = sort.or(order.map(|_| Sort::Deadline)) {
+        q.push_str(&format!(
+            " order by {}",
+            sort.order_by(order.unwrap_or(Order::Asc))
+        ));
+    }
+
+    // ---------- ここから先は時間がかかるので先に応答しておく ----------
+    if is_disp {
+        let _ = ctx.defer_ephemeral().await;
+    } else {
+        let _ = ctx.defer().await;
+    }
+
+    let rows = client.query(&q, &params).await;
+
+    // ---------- コンパクト表示 ----------
+    if view == Some(View::Compact) {
+        match rows {
+            Ok(rows) if !rows.is_empty() => {
+                paginate(ctx, compact_pages(&rows, &states), is_disp).await;
+            }
+            _ => {
                 let rep_builder = CreateReply::default()
                     .ephemeral(is_disp)
                     .content("タスクはありません\u{2615}");
                 let _ = ctx.send(rep_builder).await;
             }
         }
-        // DB内にテーブルが存在しない場合はエラーとなるので以下が実行される
-        Err(_) => {
+        return Ok(());
+    }
+
+    match rows {
+        // rows<vec>の中身が空でない場合
+        Ok(rows) if !rows.is_empty() => {
+            // Discordの上限（ボタン5行・6000文字）を超えないようにページに分ける
+            // NOTE: タグなどは開いたページの分だけ引くので, ここでは名前と概要だけで数える
+            let rows = rows
+                .into_iter()
+                .map(|row| {
+                    let name: String = row.get("task_name");
+                    let description: Option<String> = row.get("description");
+                    let len = name.chars().count()
+                        + description.map_or(0, |d| d.chars().count())
+                        + TASK_FOOTER.chars().count();
+                    (row, len)
+                })
+                .collect();
+            let pages = TaskPages {
+                client,
+                states,
+                rendered: Vec::new(),
+                chunks: split_by(rows, ACTION_EMBEDS),
+                users: HashMap::new(),
+            };
+            paginate_pages(ctx, pages, is_disp).await;
+        }
+        // rows<vec>の中身が空の場合
+        // DB内にテーブルが存在しない場合もエラーとなるのでこちら
+        _ => {
             let rep_builder = CreateReply::default()
                 .ephemeral(is_disp)
                 .content("タスクはありません\u{2615}");
@@ -0,0 +1,57 @@
+//! イベントハンドラの実装
+//!
+//! スラッシュコマンド以外のDiscordのイベントはここで受け取る.
+
+use crate::action;
+use crate::auth::is_authorized_guild;
+use crate::comment;
+use crate::forum;
+use poise::serenity_prelude::{self as serenity, FullEvent, Interaction};
+
+/// イベントを振り分ける
+pub async fn handler(
+    ctx: &serenity::Context,
+    event: &FullEvent,
+    framework: poise::FrameworkContext<'_, super::Data, serenity::Error>,
+    data: &super::Data,
+) -> Result<(), serenity::Error> {
+    match event {
+        // ---------- タスク表示への返信をコメントとして取り込む ----------
+        // NOTE: Botへのメンション付きの返信は本文が届くので, インテントとは別に設定を見る
+        FullEvent::Message { new_message } => {
+            if !data.capture_replies {
+                return Ok(());
+            }
+            let Some(guild_id) = new_message.guild_id else {
+                return Ok(());
+            };
+            if is_authorized_guild(guild_id) {
+                comment::capture_reply(ctx, framework.bot_id, new_message).await;
+            }
+        }
+        // ---------- フォーラムの投稿をタスクにする ----------
+        FullEvent::ThreadCreate { thread } => {
+            forum::on_post_created(ctx, thread).await;
+        }
+        FullEvent::ThreadUpdate { new, .. } => {
+            forum::on_post_updated(ctx, new).await;
+        }
+        // ---------- タスク表示のボタン ----------
+        FullEvent::InteractionCreate { interaction } => {
+            if !interaction.guild_id().is_some_and(is_authorized_guild) {
+                return Ok(());
+            }
+            match interaction {
+                Interaction::Component(mi) if mi.data.custom_id.starts_with(action::PREFIX) => {
+                    action::on_button(ctx, mi).await;
+                }
+                Interaction::Modal(modal) if modal.data.custom_id.starts_with(action::PREFIX) => {
+                    action::on_modal(ctx, modal).await;
+                }
+                _ => {}
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
@@ -0,0 +1,391 @@
+//! フォーラムチャンネル連携の実装
+//!
+//! `/forum link`でフォーラムをタスクボードにすると, 投稿1つがタスク1件になる.
+//! タスクは投稿（スレッド）のIDをテーブル名にして登録するので, 投稿の中で
+//! そのまま`/status`などが使える. `thread_id`列にも投稿自身のIDを入れておく.
+//!
+//! ステータスはフォーラムのタグ「未着手」「進行中」「完了済み」と対応させ,
+//! どちらから変えてももう一方に反映される.
+
+use crate::auth::is_authorized_guild;
+use crate::db::{connect_to_db, insert_task};
+use crate::imp::{self, status_name};
+use crate::support::reply;
+use crate::workflow;
+use poise::serenity_prelude::{
+    self as serenity, ChannelId, EditThread, ForumTagId, GuildChannel, Http,
+};
+use tokio_postgres::Client;
+
+pub type Context<'a> = poise::Context<'a, super::Data, serenity::Error>;
+
+/// フォーラムのステータス用タグの並び
+const STATUSES: [i16; 3] = [1, 2, 0];
+
+/// 連携しているフォーラムのステータス用タグ
+struct Board {
+    todo: ForumTagId,
+    doing: ForumTagId,
+    done: ForumTagId,
+}
+
+impl Board {
+    /// ステータスに対応するタグ
+    fn tag_of(&self, status: i16) -> ForumTagId {
+        // 独自のワークフローの状態は 完了扱い / 最初の状態 / それ以外 に寄せる
+        match status {
+            s if workflow::is_done(s) => self.done,
+            1 => self.todo,
+            _ => self.doing,
+        }
+    }
+
+    /// 投稿に付いているタグからステータスを決める
+    ///
+    /// 複数付いている場合は完了済み > 進行中 > 未着手の順で優先する.
+    fn status_of(&self, tags: &[ForumTagId]) -> Option<i16> {
+        [0, 2, 1]
+            .into_iter()
+            .find(|status| tags.contains(&self.tag_of(*status)))
+    }
+
+    fn is_status_tag(&self, tag: &ForumTagId) -> bool {
+        *tag == self.todo || *tag == self.doing || *tag == self.done
+    }
+}
+
+/// フォーラムの連携設定を取得
+async fn board(client: &Client, forum_id: &str) -> Option<Board> {
+    let rows = client
+        .query(
+            "select todo_tag, doing_tag, done_tag from kipp.forum_boards where forum_id=$1;",
+            &[&forum_id],
+        )
+        .await
+        .ok()?;
+    let row = rows.first()?;
+    let tag = |col: &str| -> Option<ForumTagId> {
+        row.get::<&str, String>(col)
+            .parse::<u64>()
+            .ok()
+            .map(ForumTagId::new)
+    };
+    Some(Board {
+        todo: tag("todo_tag")?,
+        doing: tag("doing_tag")?,
+        done: tag("done_tag")?,
+    })
+}
+
+/// 投稿が連携中のフォーラムのものならフォーラムIDを返す
+async fn forum_of(client: &Client, post_id: &str) -> Option<String> {
+    let rows = client
+        .query(
+            "select forum_id from kipp.forum_posts where post_id=$1;",
+            &[&post_id],
+        )
+        .await
+        .ok()?;
+    rows.first().map(|row| row.get("forum_id"))
+}
+
+/// フォーラムにステータス用のタグを揃える
+///
+/// 同じ名前のタグがあればそれを使い, 無ければ作る.
+async fn ensure_tags(http: &Http, forum: &GuildChannel) -> Result<Board, serenity::Error> {
+    let missing: Vec<&str> = STATUSES
+        .iter()
+        .map(|s| status_name(*s))
+        .filter(|name| !forum.available_tags.iter().any(|t| t.name == *name))
+        .collect();
+
+    // serenityのCreateForumTagはIDを持てず既存のタグが消えてしまうので, 生のJSONで送る
+    let tags = if missing.is_empty() {
+        forum.available_tags.clone()
+    } else {
+        let mut available: Vec<serde_json::Value> = forum
+            .available_tags
+            .iter()
+            .filter_map(|t| serde_json::to_value(t).ok())
+            .collect();
+        for name in missing {
+            available.push(serde_json::json!({ "name": name }));
+        }
+        http.edit_channel(
+            forum.id,
+            &serde_json::json!({ "available_tags": available }),
+            None,
+        )
+        .await?
+        .available_tags
+    };
+
+    let find = |status: i16| {
+        tags.iter()
+            .find(|t| t.name == status_name(status))
+            .map(|t| t.id)
+    };
+    match (find(1), find(2), find(0)) {
+        (Some(todo), Some(doing), Some(done)) => Ok(Board { todo, doing, done }),
+        _ => Err(serenity::Error::Other(
+            "フォーラムのタグを作成できませんでした",
+        )),
+    }
+}
+
+/// 投稿をタスクとして登録する
+///
+/// ステータス用のタグが付いていなければ「未着手」を付ける.
+async fn register_post(
+    http: &Http,
+    client: &Client,
+    forum_id: &str,
+    board: &Board,
+    post: &GuildChannel,
+) -> bool {
+    let post_id = post.id.to_string();
+    let member = post.owner_id.map(|id| id.to_string());
+    let Ok(task_id) = insert_task(client, &post_id, &post.name, &None, &member, &None).await else {
+        return false;
+    };
+
+    let status = board.status_of(&post.applied_tags);
+    let query = format!(
+        "update \"{}\" set thread_id=$1, status=$2 where id=$3;",
+        post_id
+    );
+    let _ = client
+        .execute(&query, &[&post_id, &status.unwrap_or(1), &task_id])
+        .await;
+    let _ = client
+        .execute(
+            "insert into kipp.forum_posts (post_id, forum_id) values ($1, $2)
+            on conflict do nothing;",
+            &[&post_id, &forum_id],
+        )
+        .await;
+
+    if status.is_none() {
+        let mut tags = post.applied_tags.clone();
+        tags.push(board.todo);
+        let _ = post
+            .id
+            .edit_thread(http, EditThread::new().applied_tags(tags))
+            .await;
+    }
+    true
+}
+
+/// フォーラムをタスクボードとして連携する
+///
+/// 既にある（アーカイブされていない）投稿もタスクとして取り込む.
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `forum` - 連携するフォーラムチャンネル
+pub async fn link(ctx: Context<'_>, forum: GuildChannel) -> Result<(), serenity::Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let client = connect_to_db().await?;
+    let http = ctx.http();
+
+    let board = match ensure_tags(http, &forum).await {
+        Ok(board) => board,
+        Err(e) => {
+            reply(
+                ctx,
+                "フォーラムのタグを用意できませんでした（権限を確認してください）",
+            )
+            .await;
+            return Err(e);
+        }
+    };
+
+    let forum_id = forum.id.to_string();
+    let res = client
+        .execute(
+            "insert into kipp.forum_boards (forum_id, guild_id, todo_tag, doing_tag, done_tag)
+            values ($1, $2, $3, $4, $5)
+            on conflict (forum_id) do update set todo_tag = excluded.todo_tag,
+                doing_tag = excluded.doing_tag, done_tag = excluded.done_tag;",
+            &[
+                &forum_id,
+                &guild_id.to_string(),
+                &board.todo.to_string(),
+                &board.doing.to_string(),
+                &board.done.to_string(),
+            ],
+        )
+        .await;
+    if res.is_err() {
+        reply(ctx, "フォーラムを連携できませんでした").await;
+        return Err(serenity::Error::Other("フォーラムの連携に失敗しました"));
+    }
+
+    // ---------- 既存の投稿を取り込む ----------
+    let mut count = 0;
+    let threads = guild_id.get_active_threads(http).await?;
+    for post in threads
+        .threads
+        .iter()
+        .filter(|t| t.parent_id == Some(forum.id))
+    {
+        if forum_of(&client, &post.id.to_string()).await.is_some() {
+            continue;
+        }
+        if register_post(http, &client, &forum_id, &board, post).await {
+            count += 1;
+        }
+    }
+
+    reply(
+        ctx,
+        format!(
+            "<#{}> をタスクボードにしました（既存の投稿{}件を取り込みました）",
+            forum.id, count
+        ),
+    )
+    .await;
+    Ok(())
+}
+
+/// フォーラムの連携を解除する
+///
+/// 登録済みのタスクはそのまま残る. 投稿との対応も残しておくので,
+/// もう一度連携したときに同じ投稿が二重に取り込まれることは無い.
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `forum` - 連携を解除するフォーラムチャンネル
+pub async fn unlink(ctx: Context<'_>, forum: GuildChannel) -> Result<(), serenity::Error> {
+    let client = connect_to_db().await?;
+    let forum_id = forum.id.to_string();
+
+    let res = client
+        .execute(
+            "delete from kipp.forum_boards where forum_id=$1;",
+            &[&forum_id],
+        )
+        .await;
+    match res {
+        Ok(0) => reply(ctx, "そのフォーラムは連携していません").await,
+        Ok(_) => reply(ctx, format!("<#{}> の連携を解除しました", forum.id)).await,
+        Err(_) => {
+            reply(ctx, "連携を解除できませんでした").await;
+            return Err(serenity::Error::Other("フォーラムの連携解除に失敗しました"));
+        }
+    }
+    Ok(())
+}
+
+/// 新しい投稿をタスクにする
+///
+/// `ThreadCreate`イベントから呼ぶ. 連携していないフォーラムやスレッドは無視する.
+pub async fn on_post_created(ctx: &serenity::Context, post: &GuildChannel) {
+    let Some(parent) = post.parent_id else {
+        return;
+    };
+    if !is_authorized_guild(post.guild_id) {
+        return;
+    }
+    let Ok(client) = connect_to_db().await else {
+        return;
+    };
+    let forum_id = parent.to_string();
+    let Some(board) = board(&client, &forum_id).await else {
+        return;
+    };
+    // 参加通知などで同じ投稿が2回来ることがある
+    if forum_of(&client, &post.id.to_string()).await.is_some() {
+        return;
+    }
+    register_post(&ctx.http, &client, &forum_id, &board, post).await;
+}
+
+/// 投稿のタグや名前の変更をタスクに反映する
+///
+/// `ThreadUpdate`イベントから呼ぶ.
+pub async fn on_post_updated(ctx: &serenity::Context, post: &GuildChannel) {
+    let post_id = post.id.to_string();
+    let Ok(client) = connect_to_db().await else {
+        return;
+    };
+    let Some(forum_id) = forum_of(&client, &post_id).await else {
+        return;
+    };
+    let Some(board) = board(&client, &forum_id).await else {
+        return;
+    };
+
+    let query = format!(
+        "select id, status from \"{}\" where thread_id=$1 limit 1;",
+        post_id
+    );
+    let Ok(rows) = client.query(&query, &[&post_id]).await else {
+        return;
+    };
+    let Some(row) = rows.first() else {
+        return;
+    };
+    let task_id: uuid::Uuid = row.get("id");
+    let current: i16 = row.get("status");
+
+    let query = format!("update \"{}\" set task_name=$1 where id=$2;", post_id);
+    let _ = client.execute(&query, &[&post.name, &task_id]).await;
+
+    // ---------- ステータスの反映 ----------
+    let Some(status) = board.status_of(&post.applied_tags) else {
+        return;
+    };
+    // タグが今のステータスのタグのままなら変えない.
+    // 独自のワークフローの状態はタグに寄せてあるので, ここで戻すと上書きしてしまう.
+    // `sync_tags`でタグを付け替えたときに返ってくる`ThreadUpdate`もここで止まる
+    if board.tag_of(status) == board.tag_of(current) {
+        return;
+    }
+    let query = format!("update \"{}\" set status=$1 where id=$2;", post_id);
+    if client.execute(&query, &[&status, &task_id]).await.is_ok() {
+        imp::after_status_change(&ctx.http, &client, &post_id, &task_id, status).await;
+    }
+}
+
+/// タスクのステータスを投稿のタグに反映する
+///
+/// 連携中のフォーラムの投稿でなければ何もしない.
+/// アーカイブされた投稿は編集できないので, 完了済み以外ならアーカイブも解除する.
+pub async fn sync_tags(http: &Http, client: &Client, channel_id: &str, status: i16) {
+    let Some(forum_id) = forum_of(client, channel_id).await else {
+        return;
+    };
+    let Some(board) = board(client, &forum_id).await else {
+        return;
+    };
+    let Ok(post_id) = channel_id.parse::<u64>().map(ChannelId::new) else {
+        return;
+    };
+    let Ok(post) = post_id.to_channel(http).await.map(|c| c.guild()) else {
+        return;
+    };
+    let Some(post) = post else {
+        return;
+    };
+
+    // 他のステータス用タグは外す
+    let mut tags: Vec<ForumTagId> = post
+        .applied_tags
+        .iter()
+        .filter(|t| !board.is_status_tag(t))
+        .copied()
+        .collect();
+    tags.push(board.tag_of(status));
+    if tags.len() == post.applied_tags.len() && tags.iter().all(|t| post.applied_tags.contains(t)) {
+        return;
+    }
+
+    let mut builder = EditThread::new().applied_tags(tags);
+    if !workflow::is_done(status) {
+        builder = builder.archived(false);
+    }
+    let _ = post_id.edit_thread(http, builder).await;
+}
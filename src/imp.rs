@@ -5,7 +5,7 @@
 
 use super::*;
 // use crate::auth::auth;
-use crate::db::connect_to_db;
+use crate::db::{connect_to_db, find_task, insert_task, purge_task, task_summary, task_tables};
 // use crate::Context;
 use chrono::NaiveDate;
 // use poise::serenity_prelude::model::guild;
@@ -15,6 +15,25 @@ use std::time::Duration; // タイムアウト処理用
 
 pub type Context<'a> = poise::Context<'a, super::Data, serenity::Error>;
 
+/// `/add`の追加オプション
+///
+/// タグや繰り返しなど, 後から増えたオプションをまとめたもの.
+#[derive(Default)]
+pub struct AddOptions {
+    /// カンマ区切りのタグ. レジストリに無いタグは自動登録される
+    pub tags: Option<String>,
+    /// 繰り返しルール. 書き方は`recurrence`モジュール参照
+    pub recurrence: Option<String>,
+    /// 見積もり工数（`1h30m`など）
+    pub estimate: Option<String>,
+    /// `true`ならタスク用のスレッドを作る
+    pub thread: Option<bool>,
+    /// 入れるマイルストーンの名前
+    pub milestone: Option<String>,
+    /// 優先度
+    pub priority: Option<priority::Priority>,
+}
+
 /// タスクを1件追加します
 ///
 ///
@@ -33,8 +52,8 @@ pub type Context<'a> = poise::Context<'a, super::Data, serenity::Error>;
 /// * `description` - タスクの概要や説明があれば入力
 /// * `member` - タスクの担当者を決める場合に入力
 /// * `deadline` - タスクの期限日を設定する場合は入力
+/// * `opts` - タグや繰り返しなどの追加オプション
 ///
-
 /*
     TODO: add, remove, cleanのリファクタ
     - ドキュメンテーションコメントのために分割したモジュールを再統合
@@ -45,7 +64,17 @@ pub async fn add(
     description: Option<String>,
     member: Option<serenity::Member>,
     deadline: Option<String>,
+    opts: AddOptions,
 ) -> Result<(), serenity::Error> {
+    let AddOptions {
+        tags,
+        recurrence,
+        estimate,
+        thread,
+        milestone,
+        priority,
+    } = opts;
+
     /* コマンドを実行したチャンネルのIDを取得 */
     let channel_id = ctx.channel_id();
 
@@ -81,58 +110,163 @@ pub async fn add(
     // 〆切を設定している？
     let dline: Option<NaiveDate> = if let Some(dl) = deadline {
         // フォーマットが正しい？
-        let naive_date: Option<NaiveDate> = match chrono::NaiveDate::parse_from_str(&dl, "%Y-%m-%d")
-        {
-            Ok(date) => Some(date),
-            Err(_) => None,
-        };
+        let naive_date: Option<NaiveDate> = chrono::NaiveDate::parse_from_str(&dl, "%Y-%m-%d").ok();
         naive_date
     } else {
         None
     };
-    // ---------- レコード作成用クエリ文 ----------
-    // dlineは'None'
-    // $1などはvaluesにしか使えないらしい
-    let insert = format!(
-        "insert into \"{}\" values (uuid_generate_v4(), $1, $2, $3, $4, 1);",
-        channel_id
-    );
-
-    // ---------- テーブル作成用クエリ文 ----------
-    let create = format!(
-        "create table \"{}\" (\
-            id uuid DEFAULT uuid_generate_v4(), \
-            task_name text NOT NULL, \
-            description text,\
-            member text, \
-            deadline date, \
-            status smallint DEFAULT 1);",
-        channel_id
-    );
+
+    // ---------- 見積もり工数 ----------
+    let estimate: Option<i32> = match estimate {
+        Some(e) => match timetrack::parse_duration(&e) {
+            Some(m) => Some(m),
+            None => {
+                let _ = ctx
+                    .send(
+                        CreateReply::default()
+                            .ephemeral(true)
+                            .content("見積もりは 1h30m のような形式で入力してください"),
+                    )
+                    .await;
+                return Err(serenity::Error::Other("見積もりの形式が不正です"));
+            }
+        },
+        None => None,
+    };
+
+    // ---------- 繰り返しルール ----------
+    // 〆切が無ければルールに合う今日以降の日を〆切にする
+    let dline = if let Some(rule) = &recurrence {
+        if let Err(msg) = recurrence::validate(rule) {
+            let _ = ctx
+                .send(CreateReply::default().ephemeral(true).content(msg))
+                .await;
+            return Err(serenity::Error::Other("繰り返しルールが不正です"));
+        }
+        dline.or_else(|| recurrence::first_date(rule))
+    } else {
+        dline
+    };
+    // ---------- マイルストーン ----------
+    // 登録してから失敗しないように先に確かめておく
+    let milestone_id: Option<i32> = match milestone {
+        Some(name) => {
+            let guild_id = ctx.guild_id().unwrap().to_string();
+            match milestone::find_open(&client, &guild_id, &name).await {
+                Some(id) => Some(id),
+                None => {
+                    let _ = ctx
+                        .send(
+                            CreateReply::default()
+                                .ephemeral(true)
+                                .content("受付中のそのマイルストーンはありません"),
+                        )
+                        .await;
+                    return Err(serenity::Error::Other("マイルストーンが見つかりません"));
+                }
+            }
+        }
+        None => None,
+    };
+
     // ---------- クエリ送信 ----------
-    let _res = match client
-        .query(&insert, &[&tsk_name, &description, &member_id, &dline])
-        .await
+    // テーブルが無ければ作成してから登録される
+    let task_id = match insert_task(
+        &client,
+        &channel_id.to_string(),
+        &tsk_name,
+        &description,
+        &member_id,
+        &dline,
+    )
+    .await
     {
-        Ok(_result) => {}
+        Ok(id) => id,
         Err(_e) => {
-            let _e_res = match client.query(&create, &[]).await {
-                Ok(_result) => {
-                    let res = client
-                        .query(&insert, &[&tsk_name, &description, &member_id, &dline])
-                        .await;
-                    if let Ok(_) = res {
-                    } else {
-                        return Err(serenity::Error::Other("タスクの登録に失敗しました".into()));
-                    }
-                }
-                Err(_e) => {
-                    return Err(serenity::Error::Other("タスクの登録に失敗しました".into()));
-                }
-            };
+            return Err(serenity::Error::Other("タスクの登録に失敗しました"));
         }
     };
 
+    // ---------- タグ付け ----------
+    if let Some(tags) = tags {
+        let guild_id = ctx.guild_id().unwrap().to_string();
+        let tags = tag::parse_tags(&tags);
+        if tag::attach(&client, &guild_id, &task_id, &tags)
+            .await
+            .is_err()
+        {
+            let _ = ctx
+                .send(
+                    CreateReply::default()
+                        .ephemeral(true)
+                        .content("タグを付けられませんでした"),
+                )
+                .await;
+        }
+    }
+
+    // ---------- マイルストーン登録 ----------
+    if let Some(id) = milestone_id {
+        if milestone::attach(&client, id, &task_id, &channel_id.to_string())
+            .await
+            .is_err()
+        {
+            let _ = ctx
+                .send(
+                    CreateReply::default()
+                        .ephemeral(true)
+                        .content("マイルストーンに入れられませんでした"),
+                )
+                .await;
+        }
+    }
+
+    // ---------- 見積もり登録 ----------
+    if estimate.is_some() {
+        let query = format!("update \"{}\" set estimate=$1 where id=$2;", channel_id);
+        let _ = client.execute(&query, &[&estimate, &task_id]).await;
+    }
+
+    // ---------- 優先度登録 ----------
+    if let Some(priority) = priority {
+        let query = format!("update \"{}\" set priority=$1 where id=$2;", channel_id);
+        let _ = client.execute(&query, &[&priority.code(), &task_id]).await;
+    }
+
+    // ---------- 繰り返し登録 ----------
+    if let Some(rule) = recurrence {
+        if recurrence::register(&client, &channel_id.to_string(), &task_id, &rule)
+            .await
+            .is_err()
+        {
+            let _ = ctx
+                .send(
+                    CreateReply::default()
+                        .ephemeral(true)
+                        .content("繰り返しを登録できませんでした"),
+                )
+                .await;
+        }
+    }
+
+    // ---------- スレッド作成 ----------
+    if thread == Some(true)
+        && thread::open(ctx.http(), &client, channel_id, &task_id, &tsk_name)
+            .await
+            .is_err()
+    {
+        let _ = ctx
+            .send(
+                CreateReply::default()
+                    .ephemeral(true)
+                    .content("スレッドを作成できませんでした"),
+            )
+            .await;
+    }
+
+    // ---------- ボードを更新 ----------
+    board::refresh(ctx.http(), &client, &channel_id.to_string()).await;
+
     /* 完了メッセージ */
     let rep = CreateReply::default()
         .ephemeral(true)
@@ -142,26 +276,17 @@ pub async fn add(
     Ok(())
 }
 
-/// タスクをチャンネルから削除
+/// はい/いいえのボタンで確認を取る
 ///
-/// Bot側から削除するかどうか聞いてくる. 一定時間内に応答がなければタイムアウトという
-/// 形で削除しない選択を取る.
+/// 質問を自分にだけ見せ, ボタンをチャンネルに出す. 20秒以内に押されなければ`None`.
+/// 押されたら`Some(はいかどうか)`を返す.
 ///
 /// # 引数
 ///
 /// * `ctx` - コマンド起動時の情報が入ったブツ
-/// * `task_id` - タスクのID (UUIDv4)
-
-pub async fn remove(ctx: Context<'_>, task_id: String) -> Result<(), serenity::Error> {
-    /* コマンドを実行したチャンネルのIDを取得 */
+/// * `question` - 確認の文面
+pub async fn confirm(ctx: Context<'_>, question: &str) -> Option<bool> {
     let channel_id = ctx.channel_id();
-    // ---------- DB処理 ----------
-
-    // ---------- 共通処理 ----------
-    // DBへの接続を試行
-    let client = connect_to_db().await.unwrap();
-
-    // ---------- DB処理おわり ----------
 
     // ---------- Yesボタン ----------
     let btn_yes = CreateButton::new("yes")
@@ -177,61 +302,103 @@ pub async fn remove(ctx: Context<'_>, task_id: String) -> Result<(), serenity::E
     let buttons = CreateActionRow::Buttons(vec![btn_yes, btn_no]);
     let rep2 = CreateMessage::default().components(vec![buttons]);
 
-    // ---------- 削除しますか？？？？ ----------
-    let how_del = CreateReply::default()
-        .ephemeral(true)
-        .content("本当に削除しますか？");
-    let _ = ctx.send(how_del).await;
+    // ---------- 質問 ----------
+    let how = CreateReply::default().ephemeral(true).content(question);
+    let _ = ctx.send(how).await;
 
     // ---------- ボタンハンドル送信 ----------
-    let h = channel_id.send_message(ctx, rep2).await;
-
-    let handle = match h {
-        Ok(result) => result,
-        Err(_) => panic!("送信失敗"),
-    };
+    let handle = channel_id.send_message(ctx, rep2).await.ok()?;
 
     // ---------- タイムアウトの秒数を指定 ----------
-    let mi = match handle
-        .await_component_interaction(&ctx)
+    let mi = handle
+        .await_component_interaction(ctx)
         .timeout(Duration::from_secs(20))
-        .await
-    {
-        Some(interaction) => interaction,
-        None => {
-            let _ = handle.delete(ctx).await;
+        .await;
+    let _ = handle.delete(ctx).await;
+
+    Some(mi?.data.custom_id == "yes")
+}
 
-            return Err(serenity::Error::Other("タイムアウトしました。".into()));
+/// タスクをチャンネルから削除
+///
+/// Bot側から削除するかどうか聞いてくる. 一定時間内に応答がなければタイムアウトという
+/// 形で削除しない選択を取る.
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `task_id` - タスクのID (UUIDv4)
+pub async fn remove(ctx: Context<'_>, task_id: String) -> Result<(), serenity::Error> {
+    /* コマンドを実行したチャンネルのIDを取得 */
+    let channel_id = ctx.channel_id();
+    // ---------- DB処理 ----------
+
+    // ---------- 共通処理 ----------
+    // DBへの接続を試行
+    let client = connect_to_db().await.unwrap();
+
+    // ---------- タスクがあるか確認 ----------
+    // 無いタスクで進めると「元に戻す」が前の操作を戻してしまう
+    let task_id = match find_task(&client, &channel_id.to_string(), &task_id).await {
+        Some(id) => id,
+        None => {
+            let _ = ctx
+                .send(
+                    CreateReply::default()
+                        .ephemeral(true)
+                        .content("このチャンネルにそのタスクはありません"),
+                )
+                .await;
+            return Err(serenity::Error::Other("タスクが見つかりません"));
         }
     };
 
-    let _ = handle.delete(ctx).await;
+    // ---------- DB処理おわり ----------
 
-    let id: &str = &mi.data.custom_id;
-    let _ = match id {
-        "yes" => {
+    // ---------- 削除しますか？？？？ ----------
+    let answer = match confirm(ctx, "本当に削除しますか？").await {
+        Some(answer) => answer,
+        None => return Err(serenity::Error::Other("タイムアウトしました。")),
+    };
+
+    match answer {
+        true => {
             // ========== 削除処理 ==========
 
             // 削除クエリ
-            let remove_query = format!(
-                "delete from \"{}\" where id=\'{}\';",
-                channel_id.to_string(),
-                task_id
-            );
+            let remove_query = format!("delete from \"{}\" where id=$1;", channel_id);
             // DBテーブルまるごと削除する際のクエリ（タスクが全部無くなったとき）
-            let remove_table_query = format!("drop table \"{}\";", channel_id.to_string());
+            let remove_table_query = format!("drop table \"{}\";", channel_id);
 
             // テーブルの行数を数えるクエリ
-            let count_row_query = format!("select count(*) from \"{}\"", channel_id.to_string());
+            let count_row_query = format!("select count(*) from \"{}\"", channel_id);
+
+            // ========== 元に戻せるように保存 ==========
+            undo::remember(
+                &client,
+                &ctx.author().id.to_string(),
+                &channel_id.to_string(),
+                &task_id,
+                "remove",
+            )
+            .await;
 
             // ========== 削除依頼 ==========
-            let _res = match client.query(&remove_query, &[]).await {
-                Ok(_result) => {
+            // 確認している間に消されていることもあるので, 1行消えたときだけ削除したことにする
+            match client.execute(&remove_query, &[&task_id]).await {
+                Ok(1) => {
+                    // ========== タグやチェックリストも消す ==========
+                    purge_task(&client, &task_id).await;
+
                     // ========== メッセージ送信でユーザーにお知らせ ==========
-                    let _ = channel_id
-                        .send_message(ctx, CreateMessage::default().content("削除しました"))
-                        .await
-                        .map(|_| ());
+                    let done_msg = channel_id
+                        .send_message(
+                            ctx,
+                            CreateMessage::default()
+                                .content("削除しました")
+                                .components(vec![undo::button()]),
+                        )
+                        .await;
 
                     // ========== 行数カウント ==========
                     let count_row = client.query(&count_row_query, &[]).await.unwrap();
@@ -249,23 +416,41 @@ pub async fn remove(ctx: Context<'_>, task_id: String) -> Result<(), serenity::E
                             .await
                             .map(|_| ());
                     }
+
+                    // ========== ボードを更新 ==========
+                    board::refresh(ctx.http(), &client, &channel_id.to_string()).await;
+
+                    // ========== 元に戻すボタン ==========
+                    if let Ok(mut msg) = done_msg {
+                        if !undo::offer(ctx, &msg).await {
+                            let _ = msg
+                                .edit(ctx, EditMessage::default().components(vec![]))
+                                .await;
+                        }
+                    }
                 }
 
-                Err(_e) => {
-                    return Err(serenity::Error::Other("削除できませんでした".into()));
+                _ => {
+                    // 消せなかったので保存した内容も捨てる
+                    undo::forget(&client, &ctx.author().id.to_string()).await;
+                    let _ = ctx
+                        .send(
+                            CreateReply::default()
+                                .ephemeral(true)
+                                .content("削除できませんでした"),
+                        )
+                        .await;
+                    return Err(serenity::Error::Other("削除できませんでした"));
                 }
             };
         }
-        "no" => {
+        false => {
             // ========== メッセージ送信でユーザーにお知らせ ==========
             let _ = channel_id
                 .send_message(ctx, CreateMessage::default().content("中止しました"))
                 .map(|_| ())
                 .await;
         }
-        _ => {
-            panic!("エラー");
-        }
     };
 
     Ok(())
@@ -276,12 +461,18 @@ pub async fn remove(ctx: Context<'_>, task_id: String) -> Result<(), serenity::E
 /// ステータスをどれに変更するかのプルダウンメニューが表示される.
 /// こちらも一定時間応答がないとタイムアウトする
 ///
+/// 遷移ルールがある場合は移れる状態だけをメニューに出す.
+///
 /// # 引数
 ///
 /// * `ctx` - コマンド起動時の情報が入ったブツ
 /// * `task_id` - タスクのID (UUIDv4)
-
-pub async fn status(ctx: Context<'_>, task_id: String) -> Result<(), serenity::Error> {
+/// * `comment` - 変更と一緒に残すコメント. 遷移ルールで必須になっていることがある
+pub async fn status(
+    ctx: Context<'_>,
+    task_id: String,
+    comment: Option<String>,
+) -> Result<(), serenity::Error> {
     /* コマンドを実行したチャンネルのIDを取得 */
     let channel_id = ctx.channel_id();
 
@@ -291,11 +482,53 @@ pub async fn status(ctx: Context<'_>, task_id: String) -> Result<(), serenity::E
     // ---------- ステータスリスト作成 ----------
     // (emoji->今のとこなし, lavel, value)
     // ---------- まずは項目の作成 ----------
-    let select_options = vec![
-        CreateSelectMenuOption::new("進行中", "2"),
-        CreateSelectMenuOption::new("未着手", "1"),
-        CreateSelectMenuOption::new("完了済み", "0"),
-    ];
+    // チャンネルのワークフローの状態を並べる
+    let guild_id = ctx.guild_id().map(|id| id.to_string());
+    let states = workflow::states(&client, &channel_id.to_string(), guild_id.as_deref()).await;
+
+    // ---------- 遷移ルール ----------
+    // 今の状態から移れる状態だけを並べる
+    let current: Option<i16> = match uuid::Uuid::parse_str(&task_id) {
+        Ok(id) => task_summary(&client, &channel_id.to_string(), &id)
+            .await
+            .map(|(_, status)| status),
+        Err(_) => None,
+    };
+    // 無いタスクで進めると「元に戻す」が前の操作を戻してしまう
+    if current.is_none() {
+        let _ = ctx
+            .send(
+                CreateReply::default()
+                    .ephemeral(true)
+                    .content("このチャンネルにそのタスクはありません"),
+            )
+            .await;
+        return Err(serenity::Error::Other("タスクが見つかりません"));
+    }
+    let rules = match current {
+        Some(from) => {
+            workflow::transitions(&client, &channel_id.to_string(), guild_id.as_deref(), from).await
+        }
+        None => None,
+    };
+    let select_options: Vec<CreateSelectMenuOption> = states
+        .iter()
+        .filter(|s| match &rules {
+            Some(rules) => rules.iter().any(|t| t.to == s.code),
+            None => true,
+        })
+        .map(|s| CreateSelectMenuOption::new(s.name.clone(), s.code.to_string()))
+        .collect();
+    if select_options.is_empty() {
+        let _ = ctx
+            .send(
+                CreateReply::default()
+                    .ephemeral(true)
+                    .content("このタスクの今の状態から変更できる状態はありません"),
+            )
+            .await;
+        return Err(serenity::Error::Other("変更できる状態がありません"));
+    }
 
     // ---------- セレクトメニュー作成 ----------
     let kind = CreateSelectMenuKind::String {
@@ -320,14 +553,14 @@ pub async fn status(ctx: Context<'_>, task_id: String) -> Result<(), serenity::E
 
     // ---------- タイムアウト設定 ----------
     let mi = match handle
-        .await_component_interaction(&ctx)
+        .await_component_interaction(ctx)
         .timeout(Duration::from_secs(60))
         .await
     {
         Some(interaction) => interaction,
         None => {
             let _ = handle.delete(ctx).await;
-            return Err(serenity::Error::Other("タイムアウトしました".into()));
+            return Err(serenity::Error::Other("タイムアウトしました"));
         }
     };
     let _ = handle.delete(ctx).await;
@@ -352,26 +585,84 @@ pub async fn status(ctx: Context<'_>, task_id: String) -> Result<(), serenity::E
             let _ = ctx.reply("エラー発生").await;
         }
         _ => {
+            // ---------- 遷移ルールの確認 ----------
+            if let (Some(from), Ok(to)) = (current, status.parse::<i16>()) {
+                let reject = match workflow::check_transition(&states, &rules, from, to) {
+                    Err(msg) => Some(msg),
+                    Ok(true) if comment.is_none() => Some(format!(
+                        "「{}」から「{}」にするにはコメントが必要です. /status の comment に理由を書いて実行し直してください",
+                        workflow::name_of(&states, from),
+                        workflow::name_of(&states, to)
+                    )),
+                    Ok(_) => None,
+                };
+                if let Some(msg) = reject {
+                    let _ = ctx
+                        .send(CreateReply::default().ephemeral(true).content(msg))
+                        .await;
+                    return Err(serenity::Error::Other("ステータスを変更できません"));
+                }
+            }
+
             // ---------- 反映クエリ ----------
             let status_change_query = format!(
                 "update \"{}\" set status=\'{}\' where id=\'{}\'",
-                channel_id.to_string(),
-                status,
-                task_id
+                channel_id, status, task_id
             );
+            // ---------- 元に戻せるように保存 ----------
+            if let Ok(id) = uuid::Uuid::parse_str(&task_id) {
+                undo::remember(
+                    &client,
+                    &ctx.author().id.to_string(),
+                    &channel_id.to_string(),
+                    &id,
+                    "status",
+                )
+                .await;
+            }
             // ---------- 反映依頼 ----------
             let result = client.query(&status_change_query, &[]).await;
             match result {
                 Ok(_) => {
-                    let _ = ctx
+                    if let (Ok(id), Ok(code)) =
+                        (uuid::Uuid::parse_str(&task_id), status.parse::<i16>())
+                    {
+                        after_status_change(
+                            ctx.http(),
+                            &client,
+                            &channel_id.to_string(),
+                            &id,
+                            code,
+                        )
+                        .await;
+                        // ---------- 変更の理由をコメントに残す ----------
+                        if let Some(text) = &comment {
+                            let author = ctx.author().id.to_string();
+                            let _ = comment::insert(&client, &id, &author, text.trim()).await;
+                        }
+                    }
+                    let handle = ctx
                         .send(
                             CreateReply::default()
                                 .ephemeral(true)
-                                .content("ステータスを変更しました"),
+                                .content("ステータスを変更しました")
+                                .components(vec![undo::button()]),
                         )
                         .await;
+                    // ---------- 元に戻すボタン ----------
+                    if let Ok(handle) = handle {
+                        if let Ok(msg) = handle.message().await {
+                            if !undo::offer(ctx, &msg).await {
+                                let _ = handle
+                                    .edit(ctx, CreateReply::default().components(vec![]))
+                                    .await;
+                            }
+                        }
+                    }
                 }
                 Err(_) => {
+                    // 変更できなかったので保存した内容も捨てる
+                    undo::forget(&client, &ctx.author().id.to_string()).await;
                     let _ = ctx
                         .send(
                             CreateReply::default()
@@ -386,6 +677,68 @@ pub async fn status(ctx: Context<'_>, task_id: String) -> Result<(), serenity::E
     Ok(())
 }
 
+/// 既定のワークフローでのステータスの表示名
+///
+/// チャンネルごとの表示名は`workflow::name_of`で取る.
+///
+/// NOTE: ステータス
+/// - 進行中 = 2
+/// - 未着手 = 1
+/// - 完了済み = 0
+pub fn status_name(status: i16) -> &'static str {
+    match status {
+        0 => "完了済み",
+        1 => "未着手",
+        2 => "進行中",
+        _ => "その他",
+    }
+}
+
+/// ステータスが変わった後の共通処理
+///
+/// `/status`以外（チェックリストの自動完了など）でステータスを変えたときもここを通す.
+///
+/// # 引数
+///
+/// * `http` - メッセージ送信用
+/// * `client` - DBクライアント
+/// * `channel_id` - タスクのあるチャンネルID
+/// * `task_id` - タスクのID
+/// * `status` - 変更後のステータス
+pub async fn after_status_change(
+    http: &Http,
+    client: &tokio_postgres::Client,
+    channel_id: &str,
+    task_id: &uuid::Uuid,
+    status: i16,
+) {
+    // ---------- フォーラムの投稿ならタグを付け替える ----------
+    // 完了済みだとお知らせの後にアーカイブされるので先にやる
+    forum::sync_tags(http, client, channel_id, status).await;
+
+    // ---------- タスクのスレッドへお知らせ ----------
+    let states = workflow::states_of(http, client, channel_id).await;
+    thread::notify_status(
+        http,
+        client,
+        channel_id,
+        task_id,
+        &workflow::name_of(&states, status),
+        workflow::is_done(status),
+    )
+    .await;
+
+    if workflow::is_done(status) {
+        // ---------- 完了したら依存しているタスクへお知らせ ----------
+        dependency::notify_unblocked(http, client, task_id).await;
+        // ---------- 繰り返しタスクなら次を作る ----------
+        recurrence::spawn_next(http, client, channel_id, task_id).await;
+    }
+
+    // ---------- ボードを更新 ----------
+    board::refresh(http, client, channel_id).await;
+}
+
 /// チャンネル削除等で残ったテーブルを削除する処理コマンド
 ///
 /// __*注意!*__: 非アクティブのスレッドも削除される為, 定期的にスレッドの活性化をおすすめします.
@@ -403,7 +756,7 @@ pub async fn clean(ctx: Context<'_>, password: String) -> Result<(), serenity::E
                 .content("パスワードが違います")
                 .ephemeral(true);
             let _ = ctx.send(rep).await;
-            return Err(serenity::Error::Other("削除できませんでした".into()));
+            return Err(serenity::Error::Other("削除できませんでした"));
         }
     }
 
@@ -414,51 +767,48 @@ pub async fn clean(ctx: Context<'_>, password: String) -> Result<(), serenity::E
     // WARNING: この辺怪しい
     let guild_id: GuildId = ctx.guild_id().unwrap();
 
-    let http = ctx.clone().http();
+    let http = ctx.http();
     let channels = guild_id.channels(http).await?;
     let threads = guild_id.get_active_threads(http).await?;
 
     // ギルド内の全チャンネルID取得
     // テキストチャンネルとスレッドのまとめ
     let mut threds: Vec<String> = Vec::new();
-    for (key, _) in &channels {
+    for key in channels.keys() {
         threds.push(key.to_string());
     }
     for th in &threads.threads {
         threds.push(th.id.to_string());
     }
+    // アーカイブされたフォーラムの投稿もタスクボードとして使われている
+    // アーカイブ済みの投稿は全部を取ってこられないので, フォーラムが残っていれば
+    // 登録してある投稿はすべて残す. 取得に失敗したら削除処理に移行しない
+    let forums: Vec<String> = channels
+        .iter()
+        .filter(|(_, ch)| ch.kind == ChannelType::Forum)
+        .map(|(id, _)| id.to_string())
+        .collect();
+    match client
+        .query(
+            "select post_id from kipp.forum_posts where forum_id = any($1);",
+            &[&forums],
+        )
+        .await
+    {
+        Ok(rows) => threds.extend(rows.iter().map(|row| row.get::<&str, String>("post_id"))),
+        Err(_e) => {
+            return Err(serenity::Error::Other("削除できませんでした"));
+        }
+    }
 
     // println!("{:#?}\n{:#?}", chs, threds);
 
-    // クエリ
-    // DB内のすべてのテーブル名を取得 "{}"はあとで除く
-    let all_tables_query = "select tablename from pg_tables
-    where schemaname not in('pg_catalog','information_schema')
-    order by tablename;"
-        .to_string();
-
-    // クエリ投げ
-    let tables = client.query(&all_tables_query, &[]).await;
-
+    // DB内のすべてのタスクテーブル名を取得
     // クエリ失敗したら削除処理に移行しない
-    // クエリのエラー処理と同時にテーブルをVec<String>へ
-    let tables = match tables {
-        Ok(tables) => {
-            let mut tbs: Vec<String> = Vec::new();
-            for tb in tables {
-                let tb_chid: String = tb.get("tablename");
-                // {}が帰ってきたらとばす
-                if &tb_chid == "{}" {
-                    continue;
-                }
-
-                tbs.push(tb_chid);
-            }
-            // string化したテーブルを返す
-            tbs
-        }
+    let tables = match task_tables(&client).await {
+        Ok(tables) => tables,
         Err(_e) => {
-            return Err(serenity::Error::Other("削除できませんでした".into()));
+            return Err(serenity::Error::Other("削除できませんでした"));
         }
     };
 
@@ -478,8 +828,21 @@ pub async fn clean(ctx: Context<'_>, password: String) -> Result<(), serenity::E
             }
         }
         if !is_find {
+            // タグやコメントなどタスクに付いている情報も消す
+            let ids_query = format!("select id from \"{}\";", tb);
+            if let Ok(rows) = client.query(&ids_query, &[]).await {
+                for row in rows {
+                    purge_task(&client, &row.get("id")).await;
+                }
+            }
             let delete_query = format!("drop table \"{}\";", tb);
             let _ = client.query(&delete_query, &[]).await;
+            let _ = client
+                .execute("delete from kipp.forum_posts where post_id=$1;", &[tb])
+                .await;
+            let _ = client
+                .execute("delete from kipp.boards where channel_id=$1;", &[tb])
+                .await;
             count += 1;
         }
     }
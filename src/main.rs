@@ -9,19 +9,41 @@
 //!
 //! README.md参照
 
+mod action;
 mod auth;
+mod board;
+mod bulk;
+mod checklist;
 mod commands;
+mod comment;
 mod db;
+mod dependency;
 mod disp;
+mod event;
+mod forum;
 mod imp;
+mod milestone;
+mod priority;
+mod recurrence;
+mod search;
+mod sprint;
 mod support;
+mod tag;
+mod template;
+mod thread;
+mod timetrack;
+mod transfer;
+mod undo;
+mod workflow;
 
 use poise::serenity_prelude as serenity;
 use std::env;
-use tokio;
 
 /// 他のモジュールでも使いまわす
-pub struct Data {}
+pub struct Data {
+    /// 返信をコメントとして取り込むか（`.env`の`CAPTURE_REPLIES`）
+    pub capture_replies: bool,
+}
 
 // エラーハンドル用
 //他のモジュールでも使いまわします
@@ -35,27 +57,65 @@ async fn main() -> Result<(), Error> {
     env_logger::init();
 
     let token = env::var("TOKEN").expect("missing get token");
-    let intents = serenity::GatewayIntents::non_privileged();
+    let mut intents = serenity::GatewayIntents::non_privileged();
+
+    // 返信をコメントとして取り込む場合はメッセージ本文の読み取りが必要
+    // NOTE: Developer PortalでMESSAGE CONTENT INTENTを有効にしておくこと
+    let capture_replies = env::var("CAPTURE_REPLIES").is_ok_and(|v| v == "true");
+    if capture_replies {
+        intents |= serenity::GatewayIntents::MESSAGE_CONTENT;
+    }
 
     let framework = poise::Framework::builder()
         .options(poise::FrameworkOptions {
             commands: vec![
                 commands::showall(),
                 commands::show(),
+                commands::mytasks(),
+                commands::search(),
+                commands::board(),
                 commands::status(),
                 commands::add(),
                 commands::remove(),
                 commands::clean(),
                 commands::help(),
                 commands::version(),
+                commands::tag(),
+                commands::checklist(),
+                commands::depend(),
+                commands::undepend(),
+                commands::recurring(),
+                commands::template(),
+                commands::timer(),
+                commands::log(),
+                commands::estimate(),
+                commands::report(),
+                commands::comment(),
+                commands::comments(),
+                commands::forum(),
+                commands::move_task(),
+                commands::copy(),
+                commands::moveall(),
+                commands::bulk(),
+                commands::undo(),
+                commands::workflow(),
+                commands::milestone(),
+                commands::sprint(),
+                commands::priority(),
             ],
+            event_handler: |ctx, event, framework, data| {
+                Box::pin(event::handler(ctx, event, framework, data))
+            },
             ..Default::default()
         })
-        .setup(|ctx, _ready, framework| {
+        .setup(move |ctx, _ready, framework| {
             Box::pin(async move {
                 poise::builtins::register_globally(ctx, &framework.options().commands).await?;
 
-                Ok(Data {})
+                // 管理用テーブルの作成
+                db::init().await?;
+
+                Ok(Data { capture_replies })
             })
         })
         .build();
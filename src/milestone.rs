@@ -0,0 +1,528 @@
+//! マイルストーンの実装
+//!
+//! マイルストーンはギルド単位で`kipp.milestones`に登録する.
+//! タスクとの紐付けは`kipp.milestone_tasks`で管理するので, チャンネルを跨いで
+//! 1つのマイルストーンにまとめられる. 1つのタスクが入れるマイルストーンは1つだけ.
+
+use crate::db::{connect_to_db, find_task, locate_task};
+use crate::disp::{field_lines, line_pages, paginate};
+use crate::support::{reply, short_id};
+use crate::workflow;
+use chrono::{Local, NaiveDate};
+use poise::serenity_prelude::{self as serenity, CreateEmbed};
+use poise::CreateReply;
+use tokio_postgres::Client;
+
+pub type Context<'a> = poise::Context<'a, super::Data, serenity::Error>;
+
+/// 進捗表示で並べる未完了タスクの上限
+const OPEN_LIMIT: usize = 15;
+
+/// マイルストーンに入っているタスク1件
+struct Entry {
+    task_id: uuid::Uuid,
+    channel_id: String,
+    task_name: String,
+    deadline: Option<NaiveDate>,
+    done: bool,
+}
+
+/// マイルストーンの進捗
+struct Progress {
+    entries: Vec<Entry>,
+}
+
+impl Progress {
+    fn total(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn done(&self) -> usize {
+        self.entries.iter().filter(|e| e.done).count()
+    }
+
+    /// 〆切を過ぎた未完了のタスクの数
+    fn overdue(&self, today: NaiveDate) -> usize {
+        self.entries
+            .iter()
+            .filter(|e| !e.done && e.deadline.is_some_and(|d| d < today))
+            .count()
+    }
+
+    /// 完了率（%）. タスクが無ければ0
+    fn percent(&self) -> usize {
+        match self.total() {
+            0 => 0,
+            total => self.done() * 100 / total,
+        }
+    }
+}
+
+/// `■■■□□□□□□□ 30%`形式の進捗バー
+fn bar(percent: usize) -> String {
+    let filled = percent / 10;
+    format!(
+        "{}{} {}%",
+        "■".repeat(filled),
+        "□".repeat(10 - filled),
+        percent
+    )
+}
+
+/// 名前からマイルストーンの(ID, 〆切, 閉じているか)を取得
+async fn find(
+    client: &Client,
+    guild_id: &str,
+    name: &str,
+) -> Option<(i32, Option<NaiveDate>, bool)> {
+    let rows = client
+        .query(
+            "select id, due, closed from kipp.milestones where guild_id=$1 and name=$2;",
+            &[&guild_id, &name.trim()],
+        )
+        .await
+        .ok()?;
+    rows.first()
+        .map(|row| (row.get("id"), row.get("due"), row.get("closed")))
+}
+
+/// 名前から受付中のマイルストーンのIDを取得
+///
+/// 無いか閉じていれば`None`.
+pub async fn find_open(client: &Client, guild_id: &str, name: &str) -> Option<i32> {
+    match find(client, guild_id, name).await {
+        Some((id, _, false)) => Some(id),
+        _ => None,
+    }
+}
+
+/// タスクをマイルストーンに入れる
+///
+/// 別のマイルストーンに入っていた場合は移し替える.
+///
+/// # 引数
+///
+/// * `client` - DBのクライアント
+/// * `milestone_id` - マイルストーンのID
+/// * `task_id` - タスクのID
+/// * `channel_id` - タスクのあるチャンネルID
+pub async fn attach(
+    client: &Client,
+    milestone_id: i32,
+    task_id: &uuid::Uuid,
+    channel_id: &str,
+) -> Result<(), tokio_postgres::Error> {
+    client
+        .execute(
+            "insert into kipp.milestone_tasks (task_id, milestone_id, channel_id)
+            values ($1, $2, $3)
+            on conflict (task_id) do update set milestone_id = excluded.milestone_id,
+            channel_id = excluded.channel_id;",
+            &[task_id, &milestone_id, &channel_id],
+        )
+        .await?;
+    Ok(())
+}
+
+/// マイルストーンに入っているタスクを集める
+///
+/// チャンネルごとにまとめてテーブルを1回ずつ引く.
+/// チャンネルを記録する前の紐付けは, タスクを探してチャンネルを埋めておく.
+async fn load_progress(client: &Client, milestone_id: i32) -> Progress {
+    let rows = client
+        .query(
+            "select task_id, channel_id from kipp.milestone_tasks where milestone_id=$1;",
+            &[&milestone_id],
+        )
+        .await
+        .unwrap_or_default();
+
+    // ---------- チャンネルごとにまとめる ----------
+    let mut channels: Vec<(String, Vec<uuid::Uuid>)> = Vec::new();
+    for row in rows {
+        let task_id: uuid::Uuid = row.get("task_id");
+        let channel_id = match row.get::<&str, Option<String>>("channel_id") {
+            Some(channel_id) => channel_id,
+            None => {
+                let Some(channel_id) = locate_task(client, &task_id).await else {
+                    continue;
+                };
+                let _ = client
+                    .execute(
+                        "update kipp.milestone_tasks set channel_id=$2 where task_id=$1;",
+                        &[&task_id, &channel_id],
+                    )
+                    .await;
+                channel_id
+            }
+        };
+        match channels.iter_mut().find(|(ch, _)| *ch == channel_id) {
+            Some((_, ids)) => ids.push(task_id),
+            None => channels.push((channel_id, vec![task_id])),
+        }
+    }
+
+    let mut entries: Vec<Entry> = Vec::new();
+    for (channel_id, ids) in channels {
+        let query = format!(
+            "select id, task_name, deadline, status from \"{}\" where id = any($1);",
+            channel_id
+        );
+        let Ok(rows) = client.query(&query, &[&ids]).await else {
+            continue;
+        };
+        for row in rows {
+            let status: i16 = row.get("status");
+            entries.push(Entry {
+                task_id: row.get("id"),
+                channel_id: channel_id.clone(),
+                task_name: row.get("task_name"),
+                deadline: row.get("deadline"),
+                done: workflow::is_done(status),
+            });
+        }
+    }
+    // 未完了を〆切の近い順に並べる
+    entries.sort_by_key(|e| (e.done, e.deadline.is_none(), e.deadline));
+    Progress { entries }
+}
+
+/// マイルストーン名のオートコンプリート
+///
+/// 受付中のものだけを返す.
+pub async fn autocomplete_milestone(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let Some(guild_id) = ctx.guild_id().map(|id| id.to_string()) else {
+        return Vec::new();
+    };
+    let Ok(client) = connect_to_db().await else {
+        return Vec::new();
+    };
+    client
+        .query(
+            "select name from kipp.milestones
+            where guild_id=$1 and not closed and starts_with(name, $2)
+            order by due nulls last, name limit 25;",
+            &[&guild_id, &partial.trim()],
+        )
+        .await
+        .map(|rows| rows.iter().map(|row| row.get("name")).collect())
+        .unwrap_or_default()
+}
+
+/// マイルストーンを作成する
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `name` - マイルストーン名（ギルド内で一意）
+/// * `due` - 〆切日 (YYYY-MM-DD)
+pub async fn create(
+    ctx: Context<'_>,
+    name: String,
+    due: Option<String>,
+) -> Result<(), serenity::Error> {
+    let guild_id = ctx.guild_id().unwrap().to_string();
+    let client = connect_to_db().await?;
+
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        reply(ctx, "マイルストーン名を入力してください").await;
+        return Err(serenity::Error::Other("マイルストーン名が空です"));
+    }
+    let due: Option<NaiveDate> = match due {
+        Some(d) => match NaiveDate::parse_from_str(d.trim(), "%Y-%m-%d") {
+            Ok(d) => Some(d),
+            Err(_) => {
+                reply(ctx, "〆切は YYYY-MM-DD の形式で入力してください").await;
+                return Err(serenity::Error::Other("日付の形式が不正です"));
+            }
+        },
+        None => None,
+    };
+
+    let res = client
+        .execute(
+            "insert into kipp.milestones (guild_id, name, due) values ($1, $2, $3)
+            on conflict do nothing;",
+            &[&guild_id, &name, &due],
+        )
+        .await;
+    match res {
+        Ok(0) => reply(ctx, "同じ名前のマイルストーンが既にあります").await,
+        Ok(_) => reply(ctx, format!("マイルストーン「{}」を作成しました", name)).await,
+        Err(_) => {
+            reply(ctx, "マイルストーンを作成できませんでした").await;
+            return Err(serenity::Error::Other("マイルストーンの作成に失敗しました"));
+        }
+    }
+    Ok(())
+}
+
+/// マイルストーンの一覧を進捗つきで表示する
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `closed` - `true`なら閉じたものも表示する
+pub async fn list(ctx: Context<'_>, closed: Option<bool>) -> Result<(), serenity::Error> {
+    let guild_id = ctx.guild_id().unwrap().to_string();
+    // タスクを集めるのに時間がかかるので先に応答しておく
+    let _ = ctx.defer_ephemeral().await;
+    let client = connect_to_db().await?;
+    let with_closed = closed.unwrap_or(false);
+
+    let rows = client
+        .query(
+            "select id, name, due, closed from kipp.milestones
+            where guild_id=$1 and ($2 or not closed)
+            order by closed, due nulls last, name;",
+            &[&guild_id, &with_closed],
+        )
+        .await
+        .unwrap_or_default();
+    if rows.is_empty() {
+        reply(ctx, "マイルストーンはありません").await;
+        return Ok(());
+    }
+
+    let today = Local::now().date_naive();
+    let mut lines: Vec<String> = Vec::new();
+    for row in rows {
+        let id: i32 = row.get("id");
+        let name: String = row.get("name");
+        let due: Option<NaiveDate> = row.get("due");
+        let closed: bool = row.get("closed");
+        let progress = load_progress(&client, id).await;
+
+        let mut line = format!(
+            "**{}**{}  {}/{} {}",
+            name,
+            if closed { "（クローズ）" } else { "" },
+            progress.done(),
+            progress.total(),
+            bar(progress.percent())
+        );
+        if let Some(due) = due {
+            line.push_str(&format!("  〆切: {}", due.format("%Y-%m-%d")));
+        }
+        let overdue = progress.overdue(today);
+        if 0 < overdue {
+            line.push_str(&format!("  \u{26a0}\u{fe0f} 期限切れ{}件", overdue));
+        }
+        lines.push(line);
+    }
+
+    // 数が多くても説明文の上限を超えないようにページに分ける
+    paginate(ctx, line_pages("マイルストーン", lines), true).await;
+    Ok(())
+}
+
+/// マイルストーンの進捗を表示する
+///
+/// 完了率と期限切れの数, チャンネルごとの内訳, 未完了のタスクを出す.
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `name` - マイルストーン名
+pub async fn show(ctx: Context<'_>, name: String) -> Result<(), serenity::Error> {
+    let guild_id = ctx.guild_id().unwrap().to_string();
+    let client = connect_to_db().await?;
+
+    let Some((id, due, closed)) = find(&client, &guild_id, &name).await else {
+        reply(ctx, "そのマイルストーンはありません").await;
+        return Err(serenity::Error::Other("マイルストーンが見つかりません"));
+    };
+    // タスクを集めるのに時間がかかるので先に応答しておく
+    let _ = ctx.defer_ephemeral().await;
+    let progress = load_progress(&client, id).await;
+    let today = Local::now().date_naive();
+    let overdue = progress.overdue(today);
+
+    // ---------- 〆切 ----------
+    let mut description = match due {
+        Some(due) if closed => format!("〆切: {}（クローズ）", due.format("%Y-%m-%d")),
+        Some(due) if due < today => format!(
+            "〆切: {}（{}日超過）",
+            due.format("%Y-%m-%d"),
+            (today - due).num_days()
+        ),
+        Some(due) => format!(
+            "〆切: {}（あと{}日）",
+            due.format("%Y-%m-%d"),
+            (due - today).num_days()
+        ),
+        None if closed => "〆切: なし（クローズ）".to_string(),
+        None => "〆切: なし".to_string(),
+    };
+    description.push_str(&format!("\n{}", bar(progress.percent())));
+
+    // ---------- チャンネルごとの内訳 ----------
+    let mut channels: Vec<(String, usize, usize)> = Vec::new();
+    for entry in &progress.entries {
+        match channels
+            .iter_mut()
+            .find(|(ch, _, _)| *ch == entry.channel_id)
+        {
+            Some((_, done, total)) => {
+                *done += entry.done as usize;
+                *total += 1;
+            }
+            None => channels.push((entry.channel_id.clone(), entry.done as usize, 1)),
+        }
+    }
+    let channels: Vec<String> = channels
+        .iter()
+        .map(|(ch, done, total)| format!("<#{}> {}/{}", ch, done, total))
+        .collect();
+
+    // ---------- 未完了のタスク ----------
+    let open: Vec<&Entry> = progress.entries.iter().filter(|e| !e.done).collect();
+    let open_lines: Vec<String> = open
+        .iter()
+        .map(|e| {
+            let deadline = match e.deadline {
+                Some(d) if d < today => format!(" \u{26a0}\u{fe0f} {}", d.format("%Y-%m-%d")),
+                Some(d) => format!(" {}", d.format("%Y-%m-%d")),
+                None => String::new(),
+            };
+            format!(
+                "`#{}` {} <#{}>{}",
+                short_id(&e.task_id),
+                e.task_name,
+                e.channel_id,
+                deadline
+            )
+        })
+        .collect();
+
+    let color = if closed {
+        0x808080
+    } else if 0 < overdue || due.is_some_and(|d| d < today) {
+        0xff0000
+    } else {
+        0x00ff00
+    };
+    let mut embed = CreateEmbed::default()
+        .title(format!("マイルストーン「{}」", name.trim()))
+        .description(description)
+        .color(color)
+        .field(
+            "完了",
+            format!("{} / {}", progress.done(), progress.total()),
+            true,
+        )
+        .field("期限切れ", format!("{}件", overdue), true);
+    if !channels.is_empty() {
+        embed = embed.field("チャンネル", field_lines(&channels, channels.len()), false);
+    }
+    if !open_lines.is_empty() {
+        embed = embed.field(
+            "未完了のタスク",
+            field_lines(&open_lines, OPEN_LIMIT),
+            false,
+        );
+    }
+    let _ = ctx
+        .send(CreateReply::default().ephemeral(true).embed(embed))
+        .await;
+    Ok(())
+}
+
+/// マイルストーンを閉じる
+///
+/// 閉じたマイルストーンにはタスクを追加できなくなる. タスクの紐付けは残る.
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `name` - マイルストーン名
+pub async fn close(ctx: Context<'_>, name: String) -> Result<(), serenity::Error> {
+    let guild_id = ctx.guild_id().unwrap().to_string();
+    let client = connect_to_db().await?;
+
+    let Some(id) = find_open(&client, &guild_id, &name).await else {
+        reply(ctx, "受付中のそのマイルストーンはありません").await;
+        return Err(serenity::Error::Other("マイルストーンが見つかりません"));
+    };
+    let progress = load_progress(&client, id).await;
+    if client
+        .execute(
+            "update kipp.milestones set closed=true where id=$1;",
+            &[&id],
+        )
+        .await
+        .is_err()
+    {
+        reply(ctx, "マイルストーンを閉じられませんでした").await;
+        return Err(serenity::Error::Other(
+            "マイルストーンのクローズに失敗しました",
+        ));
+    }
+
+    let mut msg = format!(
+        "マイルストーン「{}」を閉じました（{}/{} 完了）",
+        name.trim(),
+        progress.done(),
+        progress.total()
+    );
+    let open = progress.total() - progress.done();
+    if 0 < open {
+        msg.push_str(&format!("\n未完了のタスクが{}件残っています", open));
+    }
+    reply(ctx, msg).await;
+    Ok(())
+}
+
+/// このチャンネルのタスクをマイルストーンに入れる・外す
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `task_id` - タスクのID (UUIDv4)
+/// * `name` - マイルストーン名. 省略時はマイルストーンから外す
+pub async fn assign(
+    ctx: Context<'_>,
+    task_id: String,
+    name: Option<String>,
+) -> Result<(), serenity::Error> {
+    let guild_id = ctx.guild_id().unwrap().to_string();
+    let channel_id = ctx.channel_id().to_string();
+    let client = connect_to_db().await?;
+
+    let Some(task_id) = find_task(&client, &channel_id, &task_id).await else {
+        reply(ctx, "このチャンネルにそのタスクはありません").await;
+        return Err(serenity::Error::Other("タスクが見つかりません"));
+    };
+
+    let Some(name) = name else {
+        let _ = client
+            .execute(
+                "delete from kipp.milestone_tasks where task_id=$1;",
+                &[&task_id],
+            )
+            .await;
+        reply(ctx, "タスクをマイルストーンから外しました").await;
+        return Ok(());
+    };
+    let Some(id) = find_open(&client, &guild_id, &name).await else {
+        reply(ctx, "受付中のそのマイルストーンはありません").await;
+        return Err(serenity::Error::Other("マイルストーンが見つかりません"));
+    };
+    match attach(&client, id, &task_id, &channel_id).await {
+        Ok(_) => {
+            reply(
+                ctx,
+                format!("タスクをマイルストーン「{}」に入れました", name.trim()),
+            )
+            .await
+        }
+        Err(_) => {
+            reply(ctx, "マイルストーンに入れられませんでした").await;
+            return Err(serenity::Error::Other(
+                "マイルストーンへの追加に失敗しました",
+            ));
+        }
+    }
+    Ok(())
+}
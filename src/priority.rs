@@ -0,0 +1,77 @@
+//! 優先度の実装
+//!
+//! 優先度はタスクテーブルの`priority`列に数値で持つ. 大きいほど優先度が高い.
+//! 未設定は`null`.
+
+use crate::db::{connect_to_db, find_task};
+use crate::support::reply;
+use poise::serenity_prelude as serenity;
+
+pub type Context<'a> = poise::Context<'a, super::Data, serenity::Error>;
+
+/// 優先度
+#[derive(poise::ChoiceParameter, Clone, Copy)]
+pub enum Priority {
+    #[name = "高"]
+    High,
+    #[name = "中"]
+    Medium,
+    #[name = "低"]
+    Low,
+}
+
+impl Priority {
+    /// DBに入れる値
+    pub fn code(self) -> i16 {
+        match self {
+            Priority::High => 3,
+            Priority::Medium => 2,
+            Priority::Low => 1,
+        }
+    }
+}
+
+/// 優先度の表示名
+pub fn name_of(code: i16) -> &'static str {
+    match code {
+        3 => "高",
+        2 => "中",
+        1 => "低",
+        _ => "不明",
+    }
+}
+
+/// タスクの優先度を設定する
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `task_id` - タスクのID (UUIDv4)
+/// * `priority` - 優先度. 省略時は未設定に戻す
+pub async fn set(
+    ctx: Context<'_>,
+    task_id: String,
+    priority: Option<Priority>,
+) -> Result<(), serenity::Error> {
+    let channel_id = ctx.channel_id().to_string();
+    let client = connect_to_db().await?;
+
+    let Some(task_id) = find_task(&client, &channel_id, &task_id).await else {
+        reply(ctx, "このチャンネルにそのタスクはありません").await;
+        return Err(serenity::Error::Other("タスクが見つかりません"));
+    };
+
+    let code: Option<i16> = priority.map(Priority::code);
+    let query = format!("update \"{}\" set priority=$1 where id=$2;", channel_id);
+    match client.execute(&query, &[&code, &task_id]).await {
+        Ok(_) => match code {
+            Some(code) => reply(ctx, format!("優先度を「{}」にしました", name_of(code))).await,
+            None => reply(ctx, "優先度を未設定にしました").await,
+        },
+        Err(_) => {
+            reply(ctx, "優先度を設定できませんでした").await;
+            return Err(serenity::Error::Other("優先度の設定に失敗しました"));
+        }
+    }
+    Ok(())
+}
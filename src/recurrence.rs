@@ -0,0 +1,527 @@
+//! 繰り返しタスクの実装
+//!
+//! `/add`で繰り返しルールを指定すると`kipp.recurrences`に登録される.
+//! そのタスクが完了済みになると, 次の〆切で同じタスクが自動で作られる.
+//!
+//! # ルールの書き方
+//!
+//! * `daily` - 毎日
+//! * `weekly:mon,thu` - 毎週月曜と木曜. 曜日を省略すると7日ごと
+//! * `monthly:15` - 毎月15日. 月末を超える日は月末に丸める
+//! * `cron:0 9 * * 1-5` - cron形式. 日付の判定に使うのは日・月・曜日の3項目だけ
+//!
+//! 2月31日のように合う日が無いルールは登録できない.
+
+use crate::db::{connect_to_db, insert_task, task_summary};
+use crate::support::{reply, short_id};
+use chrono::{Datelike, Days, Local, NaiveDate, Weekday};
+use poise::serenity_prelude::{self as serenity, ChannelId, CreateEmbed, CreateMessage, Http};
+use poise::CreateReply;
+use tokio_postgres::Client;
+
+pub type Context<'a> = poise::Context<'a, super::Data, serenity::Error>;
+
+/// 次の日付を探すときに見る最大日数（約5年）
+const SEARCH_LIMIT: u64 = 366 * 5;
+
+/// cron形式の1項目
+///
+/// 許可される値を並べて持つ. `*`のときは`any`が立つ.
+struct CronField {
+    any: bool,
+    values: Vec<u32>,
+}
+
+impl CronField {
+    /// `*`, `1,15`, `1-5`, `*/2`, `1-10/3`を解釈する
+    fn parse(field: &str, min: u32, max: u32) -> Option<CronField> {
+        if field == "*" {
+            return Some(CronField {
+                any: true,
+                values: Vec::new(),
+            });
+        }
+        let mut values = Vec::new();
+        for part in field.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((r, s)) => (r, s.parse::<u32>().ok().filter(|s| 0 < *s)?),
+                None => (part, 1),
+            };
+            let (lo, hi) = if range == "*" {
+                (min, max)
+            } else if let Some((lo, hi)) = range.split_once('-') {
+                (lo.parse().ok()?, hi.parse().ok()?)
+            } else {
+                let v: u32 = range.parse().ok()?;
+                // `5/2`は5から上限まで
+                if part.contains('/') {
+                    (v, max)
+                } else {
+                    (v, v)
+                }
+            };
+            if lo < min || max < hi || hi < lo {
+                return None;
+            }
+            values.extend((lo..=hi).step_by(step as usize));
+        }
+        Some(CronField { any: false, values })
+    }
+
+    fn matches(&self, v: u32) -> bool {
+        self.any || self.values.contains(&v)
+    }
+}
+
+/// 繰り返しルール
+enum Rule {
+    Daily,
+    /// 空なら7日ごと
+    Weekly(Vec<Weekday>),
+    Monthly(u32),
+    Cron {
+        day: CronField,
+        month: CronField,
+        weekday: CronField,
+    },
+}
+
+/// `mon`や`月`を曜日に変換する
+fn parse_weekday(s: &str) -> Option<Weekday> {
+    match s.trim() {
+        "月" => Some(Weekday::Mon),
+        "火" => Some(Weekday::Tue),
+        "水" => Some(Weekday::Wed),
+        "木" => Some(Weekday::Thu),
+        "金" => Some(Weekday::Fri),
+        "土" => Some(Weekday::Sat),
+        "日" => Some(Weekday::Sun),
+        other => other.parse::<Weekday>().ok(),
+    }
+}
+
+impl Rule {
+    /// ルール文字列を解釈する. 解釈できなければ`None`
+    fn parse(rule: &str) -> Option<Rule> {
+        let rule = rule.trim();
+        let (kind, arg) = match rule.split_once(':') {
+            Some((k, a)) => (k.trim().to_lowercase(), a.trim()),
+            None => (rule.to_lowercase(), ""),
+        };
+        match kind.as_str() {
+            "daily" | "毎日" => Some(Rule::Daily),
+            "weekly" | "毎週" => {
+                if arg.is_empty() {
+                    return Some(Rule::Weekly(Vec::new()));
+                }
+                let days: Option<Vec<Weekday>> = arg.split(',').map(parse_weekday).collect();
+                days.map(Rule::Weekly)
+            }
+            "monthly" | "毎月" => {
+                let day: u32 = arg.parse().ok()?;
+                (1..=31).contains(&day).then_some(Rule::Monthly(day))
+            }
+            "cron" => {
+                let fields: Vec<&str> = arg.split_whitespace().collect();
+                // 5項目なら分と時を読み飛ばす
+                let fields = match fields.len() {
+                    5 => &fields[2..],
+                    3 => &fields[..],
+                    _ => return None,
+                };
+                Some(Rule::Cron {
+                    day: CronField::parse(fields[0], 1, 31)?,
+                    month: CronField::parse(fields[1], 1, 12)?,
+                    // 0と7はどちらも日曜
+                    weekday: CronField::parse(fields[2], 0, 7)?,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// `after`より後で最初にルールに合う日付
+    fn next(&self, after: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            Rule::Daily => after.checked_add_days(Days::new(1)),
+            Rule::Weekly(days) if days.is_empty() => after.checked_add_days(Days::new(7)),
+            Rule::Monthly(day) => {
+                // 翌月以降で指定日（無ければ月末）
+                let mut y = after.year();
+                let mut m = after.month();
+                loop {
+                    let last = last_day_of_month(y, m)?;
+                    let date = NaiveDate::from_ymd_opt(y, m, (*day).min(last.day()))?;
+                    if after < date {
+                        return Some(date);
+                    }
+                    (y, m) = if m == 12 { (y + 1, 1) } else { (y, m + 1) };
+                }
+            }
+            _ => (1..=SEARCH_LIMIT)
+                .filter_map(|i| after.checked_add_days(Days::new(i)))
+                .find(|date| self.matches(*date)),
+        }
+    }
+
+    fn matches(&self, date: NaiveDate) -> bool {
+        match self {
+            Rule::Weekly(days) => days.contains(&date.weekday()),
+            Rule::Cron {
+                day,
+                month,
+                weekday,
+            } => {
+                if !month.matches(date.month()) {
+                    return false;
+                }
+                let d = day.matches(date.day());
+                let wd = date.weekday().num_days_from_sunday();
+                let w = weekday.matches(wd) || (wd == 0 && weekday.matches(7));
+                // cronの慣習: 日と曜日の両方が指定されたらどちらかに合えばよい
+                if !day.any && !weekday.any {
+                    d || w
+                } else {
+                    d && w
+                }
+            }
+            _ => false,
+        }
+    }
+}
+
+fn last_day_of_month(y: i32, m: u32) -> Option<NaiveDate> {
+    let (ny, nm) = if m == 12 { (y + 1, 1) } else { (y, m + 1) };
+    NaiveDate::from_ymd_opt(ny, nm, 1)?.pred_opt()
+}
+
+/// ルール文字列を確かめる
+///
+/// 書き方が正しくても`SEARCH_LIMIT`日以内に合う日が無いルール（2月31日など）は断る.
+/// 断るときはその理由の文面を返す.
+pub fn validate(rule: &str) -> Result<(), &'static str> {
+    let Some(parsed) = Rule::parse(rule) else {
+        return Err("繰り返しの指定が正しくありません");
+    };
+    let today = Local::now().date_naive();
+    match parsed.next(today) {
+        Some(_) => Ok(()),
+        None => Err("繰り返しの指定に合う日がありません"),
+    }
+}
+
+/// ルールに合う今日以降で最初の日付
+///
+/// 〆切を指定せずに繰り返しタスクを追加したときの最初の〆切になる.
+pub fn first_date(rule: &str) -> Option<NaiveDate> {
+    let today = Local::now().date_naive();
+    Rule::parse(rule)?.next(today.pred_opt()?)
+}
+
+/// 繰り返しルールを登録する
+pub async fn register(
+    client: &Client,
+    channel_id: &str,
+    task_id: &uuid::Uuid,
+    rule: &str,
+) -> Result<(), tokio_postgres::Error> {
+    client
+        .execute(
+            "insert into kipp.recurrences (channel_id, task_id, rule) values ($1, $2, $3);",
+            &[&channel_id, task_id, &rule.trim()],
+        )
+        .await?;
+    Ok(())
+}
+
+/// 繰り返しタスクが完了したときに次のタスクを作る
+///
+/// 次の〆切は「前回の〆切」と「今日」の遅い方より後で最初にルールに合う日.
+/// タグとチェックリスト（未完了に戻す）も引き継ぐ.
+pub async fn spawn_next(http: &Http, client: &Client, channel_id: &str, task_id: &uuid::Uuid) {
+    let rows = match client
+        .query(
+            "select id, rule from kipp.recurrences where task_id=$1;",
+            &[task_id],
+        )
+        .await
+    {
+        Ok(rows) => rows,
+        Err(_) => return,
+    };
+    let Some(row) = rows.first() else {
+        return;
+    };
+    let rec_id: i32 = row.get("id");
+    let Some(rule) = Rule::parse(row.get("rule")) else {
+        return;
+    };
+
+    // ---------- 完了したタスクの中身 ----------
+    let query = format!(
+        "select task_name, description, member, deadline from \"{}\" where id=$1;",
+        channel_id
+    );
+    let Ok(rows) = client.query(&query, &[task_id]).await else {
+        return;
+    };
+    let Some(row) = rows.first() else {
+        return;
+    };
+    let task_name: String = row.get("task_name");
+    let description: Option<String> = row.get("description");
+    let member: Option<String> = row.get("member");
+    let deadline: Option<NaiveDate> = row.get("deadline");
+
+    // ---------- 次の〆切 ----------
+    let today = Local::now().date_naive();
+    let base = deadline.map_or(today, |d| d.max(today));
+    let Some(next) = rule.next(base) else {
+        return;
+    };
+
+    // ---------- 次のタスクを作成 ----------
+    let Ok(new_id) = insert_task(
+        client,
+        channel_id,
+        &task_name,
+        &description,
+        &member,
+        &Some(next),
+    )
+    .await
+    else {
+        return;
+    };
+    let _ = client
+        .execute(
+            "insert into kipp.task_tags (task_id, tag)
+            select $2, tag from kipp.task_tags where task_id=$1;",
+            &[task_id, &new_id],
+        )
+        .await;
+    let _ = client
+        .execute(
+            "insert into kipp.checklist_items (task_id, content)
+            select $2, content from kipp.checklist_items where task_id=$1 order by id;",
+            &[task_id, &new_id],
+        )
+        .await;
+    // 閉じたマイルストーンには入れない
+    let _ = client
+        .execute(
+            "insert into kipp.milestone_tasks (task_id, milestone_id, channel_id)
+            select $2, mt.milestone_id, $3 from kipp.milestone_tasks mt
+            join kipp.milestones m on m.id = mt.milestone_id
+            where mt.task_id=$1 and not m.closed;",
+            &[task_id, &new_id, &channel_id],
+        )
+        .await;
+    let _ = client
+        .execute(
+            "update kipp.recurrences set task_id=$2 where id=$1;",
+            &[&rec_id, &new_id],
+        )
+        .await;
+
+    // ---------- お知らせ ----------
+    if let Ok(ch) = channel_id.parse::<u64>() {
+        let msg = format!(
+            "\u{1f501} 繰り返しタスク「{}」を作成しました (#{} 〆切: {})",
+            task_name,
+            short_id(&new_id),
+            next.format("%Y-%m-%d")
+        );
+        let _ = ChannelId::new(ch)
+            .send_message(http, CreateMessage::default().content(msg))
+            .await;
+    }
+}
+
+/// チャンネルの繰り返しルールを一覧表示
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+pub async fn list(ctx: Context<'_>) -> Result<(), serenity::Error> {
+    let channel_id = ctx.channel_id().to_string();
+    let client = connect_to_db().await?;
+
+    let rows = client
+        .query(
+            "select id, task_id, rule from kipp.recurrences where channel_id=$1 order by id;",
+            &[&channel_id],
+        )
+        .await
+        .unwrap_or_default();
+    if rows.is_empty() {
+        reply(ctx, "繰り返しタスクはありません").await;
+        return Ok(());
+    }
+
+    let mut lines = String::new();
+    for row in rows {
+        let id: i32 = row.get("id");
+        let task_id: uuid::Uuid = row.get("task_id");
+        let rule: String = row.get("rule");
+        let name = task_summary(&client, &channel_id, &task_id)
+            .await
+            .map(|(name, _)| name)
+            .unwrap_or_else(|| "不明なタスク".to_string());
+        lines.push_str(&format!(
+            "`{}` {} (#{}) : `{}`\n",
+            id,
+            name,
+            short_id(&task_id),
+            rule
+        ));
+    }
+
+    let embed = CreateEmbed::default()
+        .title("繰り返しタスク")
+        .description(lines);
+    let _ = ctx
+        .send(CreateReply::default().ephemeral(true).embed(embed))
+        .await;
+    Ok(())
+}
+
+/// 繰り返しを止める. 今あるタスクはそのまま残る
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `id` - `/recurring list`で表示されるルールの番号
+pub async fn stop(ctx: Context<'_>, id: i32) -> Result<(), serenity::Error> {
+    let channel_id = ctx.channel_id().to_string();
+    let client = connect_to_db().await?;
+
+    let res = client
+        .execute(
+            "delete from kipp.recurrences where id=$1 and channel_id=$2;",
+            &[&id, &channel_id],
+        )
+        .await;
+    match res {
+        Ok(0) => reply(ctx, "その繰り返しタスクはありません").await,
+        Ok(_) => reply(ctx, "繰り返しを止めました").await,
+        Err(_) => {
+            reply(ctx, "繰り返しを止められませんでした").await;
+            return Err(serenity::Error::Other("繰り返しの停止に失敗しました"));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn next(rule: &str, after: NaiveDate) -> Option<NaiveDate> {
+        Rule::parse(rule).unwrap().next(after)
+    }
+
+    #[test]
+    fn daily() {
+        assert_eq!(next("daily", date(2024, 1, 31)), Some(date(2024, 2, 1)));
+        assert_eq!(next("毎日", date(2024, 12, 31)), Some(date(2025, 1, 1)));
+    }
+
+    #[test]
+    fn weekly() {
+        // 2024-01-01は月曜
+        assert_eq!(next("weekly", date(2024, 1, 1)), Some(date(2024, 1, 8)));
+        assert_eq!(
+            next("weekly:mon,thu", date(2024, 1, 1)),
+            Some(date(2024, 1, 4))
+        );
+        assert_eq!(
+            next("weekly:mon,thu", date(2024, 1, 4)),
+            Some(date(2024, 1, 8))
+        );
+        assert_eq!(next("毎週:金", date(2024, 1, 1)), Some(date(2024, 1, 5)));
+        assert!(Rule::parse("weekly:someday").is_none());
+    }
+
+    #[test]
+    fn monthly() {
+        assert_eq!(
+            next("monthly:15", date(2024, 1, 15)),
+            Some(date(2024, 2, 15))
+        );
+        assert_eq!(
+            next("monthly:15", date(2024, 1, 14)),
+            Some(date(2024, 1, 15))
+        );
+        // 月末を超える日は月末に丸める
+        assert_eq!(
+            next("monthly:31", date(2024, 1, 31)),
+            Some(date(2024, 2, 29))
+        );
+        assert_eq!(
+            next("monthly:31", date(2024, 2, 29)),
+            Some(date(2024, 3, 31))
+        );
+        assert!(Rule::parse("monthly:0").is_none());
+        assert!(Rule::parse("monthly:32").is_none());
+    }
+
+    #[test]
+    fn cron() {
+        // 2024-01-05は金曜
+        assert_eq!(
+            next("cron:0 9 * * 1-5", date(2024, 1, 5)),
+            Some(date(2024, 1, 8))
+        );
+        // 3項目でもよい
+        assert_eq!(
+            next("cron:1,15 * *", date(2024, 1, 1)),
+            Some(date(2024, 1, 15))
+        );
+        assert_eq!(
+            next("cron:0 0 */10 * *", date(2024, 1, 1)),
+            Some(date(2024, 1, 11))
+        );
+        // 日と曜日の両方を指定したらどちらかに合えばよい
+        assert_eq!(
+            next("cron:0 0 1 * 1", date(2024, 1, 2)),
+            Some(date(2024, 1, 8))
+        );
+        // 7も日曜
+        assert_eq!(
+            next("cron:0 0 * * 7", date(2024, 1, 1)),
+            Some(date(2024, 1, 7))
+        );
+        assert!(Rule::parse("cron:0 0 32 * *").is_none());
+        assert!(Rule::parse("cron:0 0").is_none());
+    }
+
+    #[test]
+    fn never_matching_rule_is_rejected() {
+        assert_eq!(next("cron:0 0 31 2 *", date(2024, 1, 1)), None);
+        assert!(validate("cron:0 0 31 2 *").is_err());
+        assert!(validate("cron:0 0 30 2 *").is_err());
+        // うるう年にだけある日は合う日がある
+        assert!(validate("cron:0 0 29 2 *").is_ok());
+    }
+
+    #[test]
+    fn validate_rules() {
+        for rule in [
+            "daily",
+            "weekly",
+            "weekly:mon,thu",
+            "monthly:31",
+            "cron:0 9 * * 1-5",
+        ] {
+            assert!(validate(rule).is_ok(), "{}", rule);
+        }
+        for rule in ["", "hourly", "weekly:x", "monthly:", "cron:* *"] {
+            assert!(validate(rule).is_err(), "{}", rule);
+        }
+    }
+}
@@ -0,0 +1,146 @@
+//! タスク検索の実装
+//!
+//! タスク名・概要・コメントをサーバー全体から探す.
+//! 日本語は単語に分けられないので, `pg_trgm`のトライグラムで部分一致と
+//! あいまい一致（`<%`）を見て, 一致度の高い順に並べる.
+//! `pg_trgm`が入っていない環境では部分一致だけで探す.
+
+use crate::db::{connect_to_db, SEARCH_TEXT};
+use crate::disp::{line_pages, paginate, visible_channels};
+use crate::support::{reply, short_id};
+use crate::workflow;
+use poise::serenity_prelude::{self as serenity, ChannelId};
+use tokio_postgres::{Client, Row};
+
+pub type Context<'a> = poise::Context<'a, super::Data, serenity::Error>;
+
+/// 表示する検索結果の上限
+const RESULT_LIMIT: usize = 50;
+
+/// 検索する文字列の上限. 結果のタイトルに入れるのでEmbedのタイトルの上限（256文字）に収める
+const QUERY_CHARS: usize = 200;
+
+/// 検索結果1件
+struct Hit {
+    channel_id: ChannelId,
+    task_id: uuid::Uuid,
+    task_name: String,
+    /// 状態名
+    status: String,
+    /// 部分一致したかどうか. あいまい一致より上に並べる
+    exact: bool,
+    /// 一致度 (0〜1)
+    score: f32,
+}
+
+/// `ilike`の特殊文字をエスケープして`%…%`にする
+fn like_pattern(query: &str) -> String {
+    let escaped = query
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+/// チャンネルのテーブルから探す
+async fn search_channel(client: &Client, channel_id: &str, query: &str) -> Vec<Row> {
+    let pattern = like_pattern(query);
+    let trigram = format!(
+        "select id, task_name, status, {0} ilike $2 as exact,
+            greatest(word_similarity($1, {0}), coalesce((select max(word_similarity($1, content))
+                from kipp.comments where task_id = t.id), 0)) as score
+        from \"{1}\" t
+        where {0} ilike $2 or $1 <% {0}
+            or id in (select task_id from kipp.comments where content ilike $2 or $1 <% content)
+        order by exact desc, score desc limit {2};",
+        SEARCH_TEXT, channel_id, RESULT_LIMIT
+    );
+    if let Ok(rows) = client.query(&trigram, &[&query, &pattern]).await {
+        return rows;
+    }
+
+    // ---------- pg_trgmが無い場合 ----------
+    let plain = format!(
+        "select id, task_name, status, {0} ilike $1 as exact, 0::real as score
+        from \"{1}\"
+        where {0} ilike $1
+            or id in (select task_id from kipp.comments where content ilike $1)
+        order by exact desc limit {2};",
+        SEARCH_TEXT, channel_id, RESULT_LIMIT
+    );
+    client.query(&plain, &[&pattern]).await.unwrap_or_default()
+}
+
+/// タスクをサーバー全体から検索する
+///
+/// 実行したユーザーが見られないチャンネルのタスクは出さない.
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `query` - 検索する文字列
+pub async fn search(ctx: Context<'_>, query: String) -> Result<(), serenity::Error> {
+    let query = query.trim().to_string();
+    if query.is_empty() {
+        reply(ctx, "検索する文字列を入力してください").await;
+        return Err(serenity::Error::Other("検索する文字列が空です"));
+    }
+    if QUERY_CHARS < query.chars().count() {
+        reply(
+            ctx,
+            format!("検索する文字列は{}文字以内で入力してください", QUERY_CHARS),
+        )
+        .await;
+        return Err(serenity::Error::Other("検索する文字列が長すぎます"));
+    }
+    let client = connect_to_db().await?;
+    let guild_id = ctx.guild_id().map(|id| id.to_string());
+
+    // ---------- チャンネルごとに探す ----------
+    let mut hits: Vec<Hit> = Vec::new();
+    for ch in visible_channels(ctx, &client).await {
+        let channel_id = ch.id.to_string();
+        let rows = search_channel(&client, &channel_id, &query).await;
+        if rows.is_empty() {
+            continue;
+        }
+        let states = workflow::states(&client, &channel_id, guild_id.as_deref()).await;
+        hits.extend(rows.iter().map(|row| Hit {
+            channel_id: ch.id,
+            task_id: row.get("id"),
+            task_name: row.get("task_name"),
+            status: workflow::name_of(&states, row.get("status")),
+            exact: row.get("exact"),
+            score: row.get("score"),
+        }));
+    }
+    if hits.is_empty() {
+        reply(ctx, format!("「{}」に一致するタスクはありません", query)).await;
+        return Ok(());
+    }
+
+    // ---------- 一致度の高い順 ----------
+    hits.sort_by(|a, b| b.exact.cmp(&a.exact).then(b.score.total_cmp(&a.score)));
+    let total = hits.len();
+    hits.truncate(RESULT_LIMIT);
+
+    let mut lines: Vec<String> = hits
+        .iter()
+        .map(|hit| {
+            format!(
+                "`#{}` **{}** \u{2014} {} \u{2014} <#{}>",
+                short_id(&hit.task_id),
+                hit.task_name,
+                hit.status,
+                hit.channel_id
+            )
+        })
+        .collect();
+    if RESULT_LIMIT < total {
+        lines.push(format!("ほか{}件", total - RESULT_LIMIT));
+    }
+
+    let title = format!("「{}」の検索結果（{} 件）", query, total);
+    paginate(ctx, line_pages(&title, lines), true).await;
+    Ok(())
+}
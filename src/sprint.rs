@@ -0,0 +1,294 @@
+//! スプリントの実装
+//!
+//! スプリントはチャンネルごとに`kipp.sprints`へ登録し, 同時に動かせるのは1つだけ.
+//! タスクがどのスプリントに入っているかはタスクテーブルの`sprint_id`列に持つので,
+//! `/show`でもそのまま絞り込める.
+//!
+//! 終了したスプリントのIDはタスクに残しておき, 持ち越すときだけ次のスプリントに付け替える.
+
+use crate::db::{connect_to_db, ensure_task_table, find_task};
+use crate::disp::field_lines;
+use crate::support::{reply, short_id};
+use crate::workflow;
+use chrono::{Local, NaiveDate};
+use poise::serenity_prelude::{self as serenity, CreateEmbed};
+use poise::CreateReply;
+use tokio_postgres::Client;
+
+pub type Context<'a> = poise::Context<'a, super::Data, serenity::Error>;
+
+/// 終了報告に並べるタスクの上限
+const REPORT_LIMIT: usize = 15;
+
+/// 動いているスプリントの(ID, 名前, 終了日)を取得
+async fn active(client: &Client, channel_id: &str) -> Option<(i32, String, NaiveDate)> {
+    let rows = client
+        .query(
+            "select id, name, end_date from kipp.sprints where channel_id=$1 and not ended;",
+            &[&channel_id],
+        )
+        .await
+        .ok()?;
+    rows.first()
+        .map(|row| (row.get("id"), row.get("name"), row.get("end_date")))
+}
+
+/// `/show`で今のスプリントに絞り込む条件
+///
+/// `$n`にはチャンネルIDを渡す.
+pub fn active_condition(n: usize) -> String {
+    format!(
+        "sprint_id = (select id from kipp.sprints where channel_id=${} and not ended)",
+        n
+    )
+}
+
+/// 終了日を解釈する. 今日より前の日付は受け付けない
+fn parse_end_date(end_date: &str) -> Option<NaiveDate> {
+    let date = NaiveDate::parse_from_str(end_date.trim(), "%Y-%m-%d").ok()?;
+    (Local::now().date_naive() <= date).then_some(date)
+}
+
+/// スプリントを登録して(ID)を返す
+async fn insert(
+    client: &Client,
+    channel_id: &str,
+    name: &str,
+    end_date: &NaiveDate,
+) -> Result<i32, tokio_postgres::Error> {
+    let rows = client
+        .query(
+            "insert into kipp.sprints (channel_id, name, end_date) values ($1, $2, $3)
+            returning id;",
+            &[&channel_id, &name, end_date],
+        )
+        .await?;
+    Ok(rows[0].get("id"))
+}
+
+/// `#1234abcd タスク名`を並べる
+fn summary(tasks: &[(uuid::Uuid, String)]) -> String {
+    if tasks.is_empty() {
+        return "なし".to_string();
+    }
+    let lines: Vec<String> = tasks
+        .iter()
+        .map(|(id, name)| format!("`#{}` {}", short_id(id), name))
+        .collect();
+    // 件数だけでなく文字数もフィールドの上限に収める
+    field_lines(&lines, REPORT_LIMIT)
+}
+
+/// スプリントを開始する
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `name` - スプリント名
+/// * `end_date` - 終了日 (YYYY-MM-DD)
+pub async fn start(
+    ctx: Context<'_>,
+    name: String,
+    end_date: String,
+) -> Result<(), serenity::Error> {
+    let channel_id = ctx.channel_id().to_string();
+    let client = connect_to_db().await?;
+
+    if let Some((_, current, _)) = active(&client, &channel_id).await {
+        reply(
+            ctx,
+            format!(
+                "スプリント「{}」が進行中です. 先に /sprint end で終了してください",
+                current
+            ),
+        )
+        .await;
+        return Err(serenity::Error::Other("スプリントが進行中です"));
+    }
+    let Some(end_date) = parse_end_date(&end_date) else {
+        reply(
+            ctx,
+            "終了日は今日以降の日付を YYYY-MM-DD の形式で入力してください",
+        )
+        .await;
+        return Err(serenity::Error::Other("日付の形式が不正です"));
+    };
+    // タスクが1件も無いチャンネルでも始められるようにテーブルを用意しておく
+    if ensure_task_table(&client, &channel_id).await.is_err()
+        || insert(&client, &channel_id, name.trim(), &end_date)
+            .await
+            .is_err()
+    {
+        reply(ctx, "スプリントを開始できませんでした").await;
+        return Err(serenity::Error::Other("スプリントの開始に失敗しました"));
+    }
+
+    let _ = ctx
+        .send(CreateReply::default().content(format!(
+            "\u{1f3c3} スプリント「{}」を開始しました（〜{}）",
+            name.trim(),
+            end_date.format("%Y-%m-%d")
+        )))
+        .await;
+    Ok(())
+}
+
+/// タスクを今のスプリントに入れる
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `task_id` - タスクのID (UUIDv4)
+pub async fn add(ctx: Context<'_>, task_id: String) -> Result<(), serenity::Error> {
+    let channel_id = ctx.channel_id().to_string();
+    let client = connect_to_db().await?;
+
+    let Some(task_id) = find_task(&client, &channel_id, &task_id).await else {
+        reply(ctx, "このチャンネルにそのタスクはありません").await;
+        return Err(serenity::Error::Other("タスクが見つかりません"));
+    };
+    let Some((sprint_id, name, _)) = active(&client, &channel_id).await else {
+        reply(ctx, "進行中のスプリントはありません").await;
+        return Err(serenity::Error::Other("スプリントがありません"));
+    };
+
+    let query = format!("update \"{}\" set sprint_id=$1 where id=$2;", channel_id);
+    match client.execute(&query, &[&sprint_id, &task_id]).await {
+        Ok(_) => reply(ctx, format!("タスクをスプリント「{}」に入れました", name)).await,
+        Err(_) => {
+            reply(ctx, "スプリントに入れられませんでした").await;
+            return Err(serenity::Error::Other("スプリントへの追加に失敗しました"));
+        }
+    }
+    Ok(())
+}
+
+/// スプリントを終了して結果を報告する
+///
+/// 次のスプリント名と終了日を指定すると, そのまま次のスプリントを開始して
+/// 未完了のタスクを持ち越す.
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `next_name` - 次のスプリント名
+/// * `next_end_date` - 次のスプリントの終了日 (YYYY-MM-DD)
+pub async fn end(
+    ctx: Context<'_>,
+    next_name: Option<String>,
+    next_end_date: Option<String>,
+) -> Result<(), serenity::Error> {
+    let channel_id = ctx.channel_id().to_string();
+    let client = connect_to_db().await?;
+
+    let Some((sprint_id, name, end_date)) = active(&client, &channel_id).await else {
+        reply(ctx, "進行中のスプリントはありません").await;
+        return Err(serenity::Error::Other("スプリントがありません"));
+    };
+
+    // ---------- 次のスプリント ----------
+    let next = match (next_name, next_end_date) {
+        (Some(name), Some(date)) => match parse_end_date(&date) {
+            Some(date) => Some((name.trim().to_string(), date)),
+            None => {
+                reply(
+                    ctx,
+                    "終了日は今日以降の日付を YYYY-MM-DD の形式で入力してください",
+                )
+                .await;
+                return Err(serenity::Error::Other("日付の形式が不正です"));
+            }
+        },
+        (None, None) => None,
+        _ => {
+            reply(
+                ctx,
+                "次のスプリントを始めるときは名前と終了日の両方を入力してください",
+            )
+            .await;
+            return Err(serenity::Error::Other(
+                "次のスプリントの指定が不足しています",
+            ));
+        }
+    };
+
+    // ---------- 完了と持ち越しに分ける ----------
+    let query = format!(
+        "select id, task_name, status from \"{}\" where sprint_id=$1 order by deadline nulls last;",
+        channel_id
+    );
+    let rows = client
+        .query(&query, &[&sprint_id])
+        .await
+        .unwrap_or_default();
+    let (done, open): (Vec<_>, Vec<_>) = rows
+        .iter()
+        .map(|row| {
+            let id: uuid::Uuid = row.get("id");
+            let task_name: String = row.get("task_name");
+            let status: i16 = row.get("status");
+            (id, task_name, workflow::is_done(status))
+        })
+        .partition(|(_, _, done)| *done);
+    let done: Vec<(uuid::Uuid, String)> = done.into_iter().map(|(id, n, _)| (id, n)).collect();
+    let open: Vec<(uuid::Uuid, String)> = open.into_iter().map(|(id, n, _)| (id, n)).collect();
+
+    if client
+        .execute(
+            "update kipp.sprints set ended=true, ended_at=current_date where id=$1;",
+            &[&sprint_id],
+        )
+        .await
+        .is_err()
+    {
+        reply(ctx, "スプリントを終了できませんでした").await;
+        return Err(serenity::Error::Other("スプリントの終了に失敗しました"));
+    }
+
+    // ---------- 次のスプリントへ持ち越し ----------
+    let mut notes: Vec<String> = Vec::new();
+    if let Some((next_name, next_end)) = &next {
+        match insert(&client, &channel_id, next_name, next_end).await {
+            Ok(next_id) => {
+                let ids: Vec<uuid::Uuid> = open.iter().map(|(id, _)| *id).collect();
+                let query = format!(
+                    "update \"{}\" set sprint_id=$1 where id = any($2);",
+                    channel_id
+                );
+                let _ = client.execute(&query, &[&next_id, &ids]).await;
+                notes.push(format!(
+                    "スプリント「{}」（〜{}）を開始しました",
+                    next_name,
+                    next_end.format("%Y-%m-%d")
+                ));
+                if !open.is_empty() {
+                    notes.push("未完了のタスクは次のスプリントに持ち越しました".to_string());
+                }
+            }
+            Err(_) => notes.push("次のスプリントを開始できませんでした".to_string()),
+        }
+    }
+
+    // ---------- 報告 ----------
+    let total = done.len() + open.len();
+    let percent = (done.len() * 100).checked_div(total).unwrap_or(0);
+    let embed = CreateEmbed::default()
+        .title(format!("スプリント「{}」終了", name))
+        .description(format!(
+            "予定終了日: {}\n完了 {}/{} ({}%)\n{}",
+            end_date.format("%Y-%m-%d"),
+            done.len(),
+            total,
+            percent,
+            notes.join("\n")
+        ))
+        .color(if open.is_empty() { 0x00ff00 } else { 0xffaa00 })
+        .field(format!("完了 ({}件)", done.len()), summary(&done), false)
+        .field(
+            format!("持ち越し ({}件)", open.len()),
+            summary(&open),
+            false,
+        );
+    let _ = ctx.send(CreateReply::default().embed(embed)).await;
+    Ok(())
+}
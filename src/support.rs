@@ -1,5 +1,6 @@
 //! サポートコマンドの実装
 
+use crate::disp::{line_pages, paginate};
 use poise::serenity_prelude::*;
 use poise::*;
 use std::env;
@@ -8,31 +9,41 @@ use std::io::{BufRead, BufReader};
 
 type Context<'a> = poise::Context<'a, super::Data, Error>;
 
-/// ヘルプの表示
-///
-/// help.txtの中身をmarkdown形式で送信
-///
 /*
     TODO: status, help, versionのリファクタ
     - ドキュメンテーションコメントのために分割したモジュールを再統合
 */
 
+/// ヘルプの表示
+///
+/// help.txtの中身をmarkdown形式で送信
+/// 1通に収まらないので, 空行で区切ったまとまりごとにコードブロックにしてページに分ける
+///
 pub async fn help(ctx: Context<'_>) -> Result<(), Error> {
     // ---------- ファイルの読み込み ----------
     let path = "help.txt";
     let input = File::open(path)?;
     let buffered: BufReader<File> = BufReader::new(input);
-    let mut res: String = String::new();
 
-    res += "```"; // Markdown形式で出力
+    // ---------- 空行ごとにまとめる ----------
+    let mut sections: Vec<String> = Vec::new();
+    let mut section: String = String::new();
     for line in buffered.lines() {
-        res += &line?;
-        res += "\n";
+        let line = line?;
+        if line.trim().is_empty() {
+            if !section.is_empty() {
+                sections.push(format!("```\n{}```", std::mem::take(&mut section)));
+            }
+            continue;
+        }
+        section += &line;
+        section += "\n";
+    }
+    if !section.is_empty() {
+        sections.push(format!("```\n{}```", section));
     }
-    res += "```"; // Markdown形式で出力
 
-    let rep_builder = CreateReply::default().ephemeral(true).content(res);
-    let _ = ctx.send(rep_builder).await;
+    paginate(ctx, line_pages("ヘルプ", sections), true).await;
     Ok(())
 }
 
@@ -42,7 +53,7 @@ pub async fn help(ctx: Context<'_>) -> Result<(), Error> {
 ///
 pub async fn version(ctx: Context<'_>) -> Result<(), Error> {
     // ---------- Cargo.toml内のバージョンを取得 ----------
-    const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+    const VERSION: &str = env!("CARGO_PKG_VERSION");
 
     let _ = ctx
         .send(CreateReply::default().ephemeral(true).content(VERSION))
@@ -50,3 +61,19 @@ pub async fn version(ctx: Context<'_>) -> Result<(), Error> {
 
     Ok(())
 }
+
+/// 自分だけに見えるメッセージで返信する
+///
+/// 送信に失敗しても無視する.
+pub async fn reply(ctx: Context<'_>, content: impl Into<String>) {
+    let _ = ctx
+        .send(CreateReply::default().ephemeral(true).content(content))
+        .await;
+}
+
+/// タスクIDの先頭8文字を返す
+///
+/// 一覧表示で`#1a2b3c4d`のように使う.
+pub fn short_id(task_id: &uuid::Uuid) -> String {
+    task_id.to_string()[..8].to_string()
+}
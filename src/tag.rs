@@ -0,0 +1,346 @@
+//! タグ関係の実装
+//!
+//! タグはギルド単位のレジストリ(`kipp.tags`)に登録され, タスクとの紐付けは`kipp.task_tags`で管理する.
+//! タスクIDはUUIDなのでチャンネルを跨いでも一意になる.
+
+use crate::db::{connect_to_db, find_task, task_tables};
+use crate::support::reply;
+use poise::serenity_prelude::{self as serenity, ChannelId, CreateEmbed};
+use poise::CreateReply;
+use tokio_postgres::Client;
+
+pub type Context<'a> = poise::Context<'a, super::Data, serenity::Error>;
+
+/// カンマ区切りのタグ文字列を分解する
+///
+/// 空白は取り除き, 空のタグと重複は無視する.
+pub fn parse_tags(tags: &str) -> Vec<String> {
+    let mut res: Vec<String> = Vec::new();
+    for tag in tags.split(',') {
+        let tag = tag.trim();
+        if tag.is_empty() || res.iter().any(|t| t == tag) {
+            continue;
+        }
+        res.push(tag.to_string());
+    }
+    res
+}
+
+/// `#ff8800`や`ff8800`形式のカラーコードを数値に変換する
+pub fn parse_color(color: &str) -> Option<i32> {
+    let color = color.trim().trim_start_matches('#');
+    if color.len() != 6 {
+        return None;
+    }
+    u32::from_str_radix(color, 16).ok().map(|c| c as i32)
+}
+
+/// ギルドのタグレジストリを取得
+///
+/// (タグ名, 色)のリストを名前順で返す.
+pub async fn registry(client: &Client, guild_id: &str) -> Vec<(String, Option<i32>)> {
+    let rows = client
+        .query(
+            "select name, color from kipp.tags where guild_id=$1 order by name;",
+            &[&guild_id],
+        )
+        .await;
+    match rows {
+        Ok(rows) => rows
+            .iter()
+            .map(|row| (row.get("name"), row.get("color")))
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// タスクに付いているタグを名前順で取得
+pub async fn task_tags(client: &Client, task_id: &uuid::Uuid) -> Vec<String> {
+    let rows = client
+        .query(
+            "select tag from kipp.task_tags where task_id=$1 order by tag;",
+            &[task_id],
+        )
+        .await;
+    match rows {
+        Ok(rows) => rows.iter().map(|row| row.get("tag")).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// タスクにタグを付ける
+///
+/// レジストリに無いタグは色なしで自動登録する.
+pub async fn attach(
+    client: &Client,
+    guild_id: &str,
+    task_id: &uuid::Uuid,
+    tags: &[String],
+) -> Result<(), tokio_postgres::Error> {
+    for tag in tags {
+        client
+            .execute(
+                "insert into kipp.tags (guild_id, name) values ($1, $2) on conflict do nothing;",
+                &[&guild_id, tag],
+            )
+            .await?;
+        client
+            .execute(
+                "insert into kipp.task_tags (task_id, tag) values ($1, $2) on conflict do nothing;",
+                &[task_id, tag],
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+/// タグのオートコンプリート
+///
+/// レジストリから入力中の文字列で始まるタグを返す.
+pub async fn autocomplete_tag(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let guild_id = match ctx.guild_id() {
+        Some(id) => id.to_string(),
+        None => return Vec::new(),
+    };
+    let client = match connect_to_db().await {
+        Ok(client) => client,
+        Err(_) => return Vec::new(),
+    };
+
+    registry(&client, &guild_id)
+        .await
+        .into_iter()
+        .map(|(name, _)| name)
+        .filter(|name| name.starts_with(partial.trim()))
+        .take(25) // Discordの候補上限
+        .collect()
+}
+
+/// カンマ区切りのタグ入力用オートコンプリート
+///
+/// 最後のカンマより後ろを補完し, それより前の入力はそのまま残す.
+pub async fn autocomplete_tags(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let (head, last) = match partial.rfind(',') {
+        Some(i) => (&partial[..=i], &partial[i + 1..]),
+        None => ("", partial),
+    };
+    let entered = parse_tags(head);
+
+    autocomplete_tag(ctx, last)
+        .await
+        .into_iter()
+        .filter(|name| !entered.contains(name))
+        .map(|name| format!("{}{}", head, name))
+        .collect()
+}
+
+/// タスクにタグを付ける
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `task_id` - タスクのID (UUIDv4)
+/// * `tag` - 付けるタグ
+pub async fn add(ctx: Context<'_>, task_id: String, tag: String) -> Result<(), serenity::Error> {
+    let channel_id = ctx.channel_id().to_string();
+    let guild_id = ctx.guild_id().unwrap().to_string();
+    let client = connect_to_db().await?;
+
+    // ---------- タスクの存在確認 ----------
+    let task_id = match find_task(&client, &channel_id, &task_id).await {
+        Some(id) => id,
+        None => {
+            reply(ctx, "このチャンネルにそのタスクはありません").await;
+            return Err(serenity::Error::Other("タスクが見つかりません"));
+        }
+    };
+
+    let tags = parse_tags(&tag);
+    if tags.is_empty() {
+        reply(ctx, "タグ名を入力してください").await;
+        return Err(serenity::Error::Other("タグが空です"));
+    }
+
+    match attach(&client, &guild_id, &task_id, &tags).await {
+        Ok(_) => {
+            reply(ctx, format!("タグ「{}」を付けました", tags.join(", "))).await;
+            Ok(())
+        }
+        Err(_) => {
+            reply(ctx, "タグを付けられませんでした").await;
+            Err(serenity::Error::Other("タグの登録に失敗しました"))
+        }
+    }
+}
+
+/// タスクからタグを外す
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `task_id` - タスクのID (UUIDv4)
+/// * `tag` - 外すタグ
+pub async fn remove(ctx: Context<'_>, task_id: String, tag: String) -> Result<(), serenity::Error> {
+    let channel_id = ctx.channel_id().to_string();
+    let client = connect_to_db().await?;
+
+    let task_id = match find_task(&client, &channel_id, &task_id).await {
+        Some(id) => id,
+        None => {
+            reply(ctx, "このチャンネルにそのタスクはありません").await;
+            return Err(serenity::Error::Other("タスクが見つかりません"));
+        }
+    };
+
+    let res = client
+        .execute(
+            "delete from kipp.task_tags where task_id=$1 and tag=$2;",
+            &[&task_id, &tag.trim()],
+        )
+        .await;
+    match res {
+        Ok(0) => reply(ctx, "そのタグは付いていません").await,
+        Ok(_) => reply(ctx, format!("タグ「{}」を外しました", tag.trim())).await,
+        Err(_) => {
+            reply(ctx, "タグを外せませんでした").await;
+            return Err(serenity::Error::Other("タグの削除に失敗しました"));
+        }
+    }
+    Ok(())
+}
+
+/// タグをレジストリに登録する. 既にある場合は色だけ更新する.
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `name` - タグ名
+/// * `color` - `#RRGGBB`形式の色（任意）
+pub async fn create(
+    ctx: Context<'_>,
+    name: String,
+    color: Option<String>,
+) -> Result<(), serenity::Error> {
+    let guild_id = ctx.guild_id().unwrap().to_string();
+    let client = connect_to_db().await?;
+
+    let name = name.trim().to_string();
+    if name.is_empty() || name.contains(',') {
+        reply(ctx, "タグ名が不正です（カンマは使えません）").await;
+        return Err(serenity::Error::Other("タグ名が不正です"));
+    }
+
+    // ---------- 色の解釈 ----------
+    let color: Option<i32> = match color {
+        Some(c) => match parse_color(&c) {
+            Some(c) => Some(c),
+            None => {
+                reply(ctx, "色は #RRGGBB の形式で入力してください").await;
+                return Err(serenity::Error::Other("色の形式が不正です"));
+            }
+        },
+        None => None,
+    };
+
+    let res = client
+        .execute(
+            "insert into kipp.tags (guild_id, name, color) values ($1, $2, $3)
+            on conflict (guild_id, name) do update set color = excluded.color;",
+            &[&guild_id, &name, &color],
+        )
+        .await;
+    match res {
+        Ok(_) => reply(ctx, format!("タグ「{}」を登録しました", name)).await,
+        Err(_) => {
+            reply(ctx, "タグを登録できませんでした").await;
+            return Err(serenity::Error::Other("タグの登録に失敗しました"));
+        }
+    }
+    Ok(())
+}
+
+/// このギルドのチャンネルのタスクテーブル
+///
+/// テーブル名のチャンネルを取得してギルドを確かめる. 取得できないチャンネルは含めない.
+async fn guild_tables(ctx: Context<'_>, client: &Client) -> Vec<String> {
+    let guild_id = ctx.guild_id();
+    let mut tables: Vec<String> = Vec::new();
+    for table in task_tables(client).await.unwrap_or_default() {
+        let Ok(id) = table.parse::<u64>() else {
+            continue;
+        };
+        let channel = ChannelId::new(id).to_channel(ctx).await;
+        if channel.is_ok_and(|ch| ch.guild().map(|ch| ch.guild_id) == guild_id) {
+            tables.push(table);
+        }
+    }
+    tables
+}
+
+/// タグをレジストリから削除する. 付いているタスクからも外れる.
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `name` - タグ名
+pub async fn delete(ctx: Context<'_>, name: String) -> Result<(), serenity::Error> {
+    let guild_id = ctx.guild_id().unwrap().to_string();
+    let client = connect_to_db().await?;
+    let name = name.trim();
+
+    let res = client
+        .execute(
+            "delete from kipp.tags where guild_id=$1 and name=$2;",
+            &[&guild_id, &name],
+        )
+        .await;
+    match res {
+        Ok(0) => reply(ctx, "そのタグは登録されていません").await,
+        Ok(_) => {
+            // 同じ名前のタグが他のギルドにもあるので, このギルドのタスクからだけ外す
+            for channel_id in guild_tables(ctx, &client).await {
+                let query = format!(
+                    "delete from kipp.task_tags where tag=$1 and task_id in (select id from \"{}\");",
+                    channel_id
+                );
+                let _ = client.execute(&query, &[&name]).await;
+            }
+            reply(ctx, format!("タグ「{}」を削除しました", name)).await;
+        }
+        Err(_) => {
+            reply(ctx, "タグを削除できませんでした").await;
+            return Err(serenity::Error::Other("タグの削除に失敗しました"));
+        }
+    }
+    Ok(())
+}
+
+/// 登録済みのタグを一覧表示
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+pub async fn list(ctx: Context<'_>) -> Result<(), serenity::Error> {
+    let guild_id = ctx.guild_id().unwrap().to_string();
+    let client = connect_to_db().await?;
+
+    let tags = registry(&client, &guild_id).await;
+    if tags.is_empty() {
+        reply(ctx, "タグは登録されていません").await;
+        return Ok(());
+    }
+
+    let mut lines = String::new();
+    for (name, color) in tags {
+        match color {
+            Some(c) => lines.push_str(&format!("`{}` #{:06x}\n", name, c)),
+            None => lines.push_str(&format!("`{}`\n", name)),
+        }
+    }
+
+    let embed = CreateEmbed::default().title("タグ一覧").description(lines);
+    let _ = ctx
+        .send(CreateReply::default().ephemeral(true).embed(embed))
+        .await;
+    Ok(())
+}
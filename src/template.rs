@@ -0,0 +1,308 @@
+//! タスクテンプレートの実装
+//!
+//! チャンネルのタスクをまとめてテンプレートとして保存し, 別のチャンネルで一括作成できる.
+//! 〆切は基準日からの日数で保存するので, 適用するときの基準日に合わせてずれる.
+
+use crate::db::{connect_to_db, insert_task};
+use crate::support::reply;
+use chrono::{Local, NaiveDate, TimeDelta};
+use poise::serenity_prelude::{self as serenity, CreateEmbed};
+use poise::CreateReply;
+
+pub type Context<'a> = poise::Context<'a, super::Data, serenity::Error>;
+
+/// `YYYY-MM-DD`形式の基準日を解釈する. 省略時は今日
+fn parse_base_date(base_date: Option<String>) -> Option<NaiveDate> {
+    match base_date {
+        Some(d) => NaiveDate::parse_from_str(d.trim(), "%Y-%m-%d").ok(),
+        None => Some(Local::now().date_naive()),
+    }
+}
+
+/// テンプレート名のオートコンプリート
+pub async fn autocomplete_template(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let guild_id = match ctx.guild_id() {
+        Some(id) => id.to_string(),
+        None => return Vec::new(),
+    };
+    let client = match connect_to_db().await {
+        Ok(client) => client,
+        Err(_) => return Vec::new(),
+    };
+    let rows = client
+        .query(
+            "select name from kipp.templates where guild_id=$1 and starts_with(name, $2)
+            order by name limit 25;",
+            &[&guild_id, &partial.trim()],
+        )
+        .await
+        .unwrap_or_default();
+    rows.iter().map(|row| row.get("name")).collect()
+}
+
+/// チャンネルの未完了タスクをテンプレートとして保存する
+///
+/// 同じ名前のテンプレートがあれば上書きする.
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `name` - テンプレート名
+/// * `base_date` - 〆切の基準日 (YYYY-MM-DD). 省略時は今日
+pub async fn save(
+    ctx: Context<'_>,
+    name: String,
+    base_date: Option<String>,
+) -> Result<(), serenity::Error> {
+    let channel_id = ctx.channel_id().to_string();
+    let guild_id = ctx.guild_id().unwrap().to_string();
+    let mut client = connect_to_db().await?;
+    let name = name.trim().to_string();
+
+    let Some(base) = parse_base_date(base_date) else {
+        reply(ctx, "基準日は YYYY-MM-DD の形式で入力してください").await;
+        return Err(serenity::Error::Other("基準日の形式が不正です"));
+    };
+
+    // ---------- チャンネルのタスクを取得 ----------
+    let query = format!(
+        "select task_name, description, member, deadline from \"{}\" where status > 0;",
+        channel_id
+    );
+    let rows = client.query(&query, &[]).await.unwrap_or_default();
+    if rows.is_empty() {
+        reply(ctx, "保存できるタスクがありません").await;
+        return Err(serenity::Error::Other("タスクがありません"));
+    }
+
+    // ---------- まとめて保存 ----------
+    let res: Result<(), tokio_postgres::Error> = async {
+        let tx = client.transaction().await?;
+        tx.execute(
+            "delete from kipp.template_items where guild_id=$1 and template=$2;",
+            &[&guild_id, &name],
+        )
+        .await?;
+        tx.execute(
+            "insert into kipp.templates (guild_id, name) values ($1, $2) on conflict do nothing;",
+            &[&guild_id, &name],
+        )
+        .await?;
+        for row in &rows {
+            let task_name: String = row.get("task_name");
+            let description: Option<String> = row.get("description");
+            let member: Option<String> = row.get("member");
+            let deadline: Option<NaiveDate> = row.get("deadline");
+            let offset: Option<i32> = deadline.map(|d| (d - base).num_days() as i32);
+            tx.execute(
+                "insert into kipp.template_items
+                (guild_id, template, task_name, description, member, offset_days)
+                values ($1, $2, $3, $4, $5, $6);",
+                &[&guild_id, &name, &task_name, &description, &member, &offset],
+            )
+            .await?;
+        }
+        tx.commit().await
+    }
+    .await;
+
+    match res {
+        Ok(_) => {
+            reply(
+                ctx,
+                format!(
+                    "テンプレート「{}」にタスクを{}件保存しました",
+                    name,
+                    rows.len()
+                ),
+            )
+            .await
+        }
+        Err(_) => {
+            reply(ctx, "テンプレートを保存できませんでした").await;
+            return Err(serenity::Error::Other("テンプレートの保存に失敗しました"));
+        }
+    }
+    Ok(())
+}
+
+/// テンプレートの一覧, または1つのテンプレートの中身を表示
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `name` - 中身を見たいテンプレート名（任意）
+pub async fn list(ctx: Context<'_>, name: Option<String>) -> Result<(), serenity::Error> {
+    let guild_id = ctx.guild_id().unwrap().to_string();
+    let client = connect_to_db().await?;
+
+    let (title, lines) = match name {
+        // ---------- テンプレートの一覧 ----------
+        None => {
+            let rows = client
+                .query(
+                    "select t.name, count(i.id) as count from kipp.templates t
+                    left join kipp.template_items i
+                    on i.guild_id = t.guild_id and i.template = t.name
+                    where t.guild_id=$1 group by t.name order by t.name;",
+                    &[&guild_id],
+                )
+                .await
+                .unwrap_or_default();
+            let lines: Vec<String> = rows
+                .iter()
+                .map(|row| {
+                    format!(
+                        "`{}` : {} 件",
+                        row.get::<&str, String>("name"),
+                        row.get::<&str, i64>("count")
+                    )
+                })
+                .collect();
+            ("テンプレート一覧".to_string(), lines)
+        }
+        // ---------- テンプレートの中身 ----------
+        Some(name) => {
+            let rows = client
+                .query(
+                    "select task_name, offset_days from kipp.template_items
+                    where guild_id=$1 and template=$2 order by id;",
+                    &[&guild_id, &name.trim()],
+                )
+                .await
+                .unwrap_or_default();
+            let lines: Vec<String> = rows
+                .iter()
+                .map(|row| {
+                    let task_name: String = row.get("task_name");
+                    match row.get::<&str, Option<i32>>("offset_days") {
+                        Some(d) => format!("- {} （基準日{:+}日）", task_name, d),
+                        None => format!("- {}", task_name),
+                    }
+                })
+                .collect();
+            (format!("テンプレート「{}」", name.trim()), lines)
+        }
+    };
+
+    if lines.is_empty() {
+        reply(ctx, "テンプレートはありません").await;
+        return Ok(());
+    }
+
+    let embed = CreateEmbed::default()
+        .title(title)
+        .description(lines.join("\n"));
+    let _ = ctx
+        .send(CreateReply::default().ephemeral(true).embed(embed))
+        .await;
+    Ok(())
+}
+
+/// テンプレートを削除
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `name` - テンプレート名
+pub async fn delete(ctx: Context<'_>, name: String) -> Result<(), serenity::Error> {
+    let guild_id = ctx.guild_id().unwrap().to_string();
+    let client = connect_to_db().await?;
+    let name = name.trim();
+
+    let res = client
+        .execute(
+            "delete from kipp.templates where guild_id=$1 and name=$2;",
+            &[&guild_id, &name],
+        )
+        .await;
+    match res {
+        Ok(0) => reply(ctx, "そのテンプレートはありません").await,
+        Ok(_) => {
+            let _ = client
+                .execute(
+                    "delete from kipp.template_items where guild_id=$1 and template=$2;",
+                    &[&guild_id, &name],
+                )
+                .await;
+            reply(ctx, format!("テンプレート「{}」を削除しました", name)).await;
+        }
+        Err(_) => {
+            reply(ctx, "テンプレートを削除できませんでした").await;
+            return Err(serenity::Error::Other("テンプレートの削除に失敗しました"));
+        }
+    }
+    Ok(())
+}
+
+/// テンプレートのタスクをこのチャンネルへまとめて作成する
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `name` - テンプレート名
+/// * `base_date` - 〆切の基準日 (YYYY-MM-DD). 省略時は今日
+pub async fn apply(
+    ctx: Context<'_>,
+    name: String,
+    base_date: Option<String>,
+) -> Result<(), serenity::Error> {
+    let channel_id = ctx.channel_id().to_string();
+    let guild_id = ctx.guild_id().unwrap().to_string();
+    let client = connect_to_db().await?;
+    let name = name.trim();
+
+    let Some(base) = parse_base_date(base_date) else {
+        reply(ctx, "基準日は YYYY-MM-DD の形式で入力してください").await;
+        return Err(serenity::Error::Other("基準日の形式が不正です"));
+    };
+
+    let rows = client
+        .query(
+            "select task_name, description, member, offset_days from kipp.template_items
+            where guild_id=$1 and template=$2 order by id;",
+            &[&guild_id, &name],
+        )
+        .await
+        .unwrap_or_default();
+    if rows.is_empty() {
+        reply(ctx, "そのテンプレートはありません").await;
+        return Err(serenity::Error::Other("テンプレートが見つかりません"));
+    }
+
+    // ---------- 1件ずつ作成 ----------
+    let mut count = 0;
+    for row in &rows {
+        let task_name: String = row.get("task_name");
+        let description: Option<String> = row.get("description");
+        let member: Option<String> = row.get("member");
+        let offset: Option<i32> = row.get("offset_days");
+        let deadline = offset.map(|d| base + TimeDelta::days(d as i64));
+
+        if insert_task(
+            &client,
+            &channel_id,
+            &task_name,
+            &description,
+            &member,
+            &deadline,
+        )
+        .await
+        .is_ok()
+        {
+            count += 1;
+        }
+    }
+
+    reply(
+        ctx,
+        format!(
+            "テンプレート「{}」からタスクを{}件作成しました（基準日: {}）",
+            name,
+            count,
+            base.format("%Y-%m-%d")
+        ),
+    )
+    .await;
+    Ok(())
+}
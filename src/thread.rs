@@ -0,0 +1,105 @@
+//! タスクごとのスレッドの実装
+//!
+//! `/add`で`thread`を指定すると, 登録メッセージからスレッドを作ってタスクの
+//! `thread_id`列に保存する. ステータスが変わるとスレッドへお知らせし,
+//! 完了済みになったらアーカイブする.
+
+use poise::serenity_prelude::{
+    self as serenity, AutoArchiveDuration, ChannelId, CreateMessage, CreateThread, EditThread, Http,
+};
+use tokio_postgres::Client;
+
+/// スレッド名の上限（Discordの仕様）
+const NAME_LIMIT: usize = 100;
+
+/// タスク用のスレッドを作成して, タスクにスレッドIDを保存する
+///
+/// 登録お知らせのメッセージをチャンネルに送り, そこからスレッドを生やす.
+pub async fn open(
+    http: &Http,
+    client: &Client,
+    channel_id: ChannelId,
+    task_id: &uuid::Uuid,
+    task_name: &str,
+) -> Result<ChannelId, serenity::Error> {
+    // ---------- スレッドの元になるメッセージ ----------
+    let msg = channel_id
+        .send_message(
+            http,
+            CreateMessage::default().content(format!(
+                "\u{1f4cc} タスク「{}」を登録しました\nタスクID: `{}`",
+                task_name, task_id
+            )),
+        )
+        .await?;
+
+    // ---------- スレッド作成 ----------
+    let name: String = task_name.chars().take(NAME_LIMIT).collect();
+    let thread = channel_id
+        .create_thread_from_message(
+            http,
+            msg.id,
+            CreateThread::new(name).auto_archive_duration(AutoArchiveDuration::OneWeek),
+        )
+        .await?;
+
+    let query = format!("update \"{}\" set thread_id=$1 where id=$2;", channel_id);
+    if client
+        .execute(&query, &[&thread.id.to_string(), task_id])
+        .await
+        .is_err()
+    {
+        return Err(serenity::Error::Other("スレッドIDの保存に失敗しました"));
+    }
+    Ok(thread.id)
+}
+
+/// タスクのスレッドIDを取得
+pub async fn thread_of(
+    client: &Client,
+    channel_id: &str,
+    task_id: &uuid::Uuid,
+) -> Option<ChannelId> {
+    let query = format!("select thread_id from \"{}\" where id=$1;", channel_id);
+    let rows = client.query(&query, &[task_id]).await.ok()?;
+    let thread_id: Option<String> = rows.first()?.get("thread_id");
+    thread_id?.parse::<u64>().ok().map(ChannelId::new)
+}
+
+/// ステータスの変更をスレッドへお知らせする
+///
+/// 完了済みになった場合はお知らせの後にスレッドをアーカイブする.
+/// 完了済み以外に戻った場合はアーカイブを解除する.
+pub async fn notify_status(
+    http: &Http,
+    client: &Client,
+    channel_id: &str,
+    task_id: &uuid::Uuid,
+    status_name: &str,
+    is_done: bool,
+) {
+    let Some(thread) = thread_of(client, channel_id, task_id).await else {
+        return;
+    };
+
+    // アーカイブ済みのスレッドには投稿できないので先に戻す
+    if !is_done {
+        let _ = thread
+            .edit_thread(http, EditThread::new().archived(false))
+            .await;
+    }
+
+    let _ = thread
+        .send_message(
+            http,
+            CreateMessage::default()
+                .content(format!("ステータスが「{}」に変わりました", status_name)),
+        )
+        .await;
+
+    if is_done {
+        let _ = thread
+            .edit_thread(http, EditThread::new().archived(true))
+            .await;
+    }
+}
@@ -0,0 +1,356 @@
+//! 工数（見積もり・作業時間）の実装
+//!
+//! 見積もりはタスクテーブルの`estimate`列（分）に持つ.
+//! 作業時間は`/timer`か`/log`で`kipp.time_logs`にユーザーごとに記録する.
+//! 動いているタイマーは`kipp.timers`に1ユーザー1つまで.
+
+use crate::db::{connect_to_db, find_task, locate_task, task_summary};
+use crate::support::{reply, short_id};
+use chrono::{Datelike, Local, NaiveDate};
+use poise::serenity_prelude::{self as serenity, CreateEmbed};
+use poise::CreateReply;
+use tokio_postgres::Client;
+
+pub type Context<'a> = poise::Context<'a, super::Data, serenity::Error>;
+
+/// `1h30m`, `45m`, `2h`, `1.5h`, `90`（分）形式の時間を分に変換する
+pub fn parse_duration(input: &str) -> Option<i32> {
+    let input = input.trim().to_lowercase();
+    // 数字だけなら分
+    if let Ok(m) = input.parse::<i32>() {
+        return (0 < m).then_some(m);
+    }
+
+    let mut total: f64 = 0.0;
+    let mut num = String::new();
+    for c in input.chars() {
+        match c {
+            '0'..='9' | '.' => num.push(c),
+            'h' | 'm' => {
+                let v: f64 = num.parse().ok()?;
+                total += if c == 'h' { v * 60.0 } else { v };
+                num.clear();
+            }
+            ' ' => {}
+            _ => return None,
+        }
+    }
+    // 単位の無い数字が残っていたら不正
+    if !num.is_empty() {
+        return None;
+    }
+    let total = total.round() as i32;
+    (0 < total).then_some(total)
+}
+
+/// 分を`1h30m`形式にする
+pub fn format_minutes(minutes: i64) -> String {
+    match (minutes / 60, minutes % 60) {
+        (0, m) => format!("{}m", m),
+        (h, 0) => format!("{}h", h),
+        (h, m) => format!("{}h{}m", h, m),
+    }
+}
+
+/// タスクに記録された作業時間の合計（分）
+pub async fn logged_minutes(client: &Client, task_id: &uuid::Uuid) -> i64 {
+    client
+        .query(
+            "select coalesce(sum(minutes), 0)::bigint as total from kipp.time_logs where task_id=$1;",
+            &[task_id],
+        )
+        .await
+        .map(|rows| rows[0].get("total"))
+        .unwrap_or(0)
+}
+
+/// `見積 2h / 実績 1h30m`形式の文字列
+///
+/// 見積もりも作業記録も無ければ`None`.
+pub async fn summary(
+    client: &Client,
+    task_id: &uuid::Uuid,
+    estimate: Option<i32>,
+) -> Option<String> {
+    let logged = logged_minutes(client, task_id).await;
+    match (estimate, logged) {
+        (None, 0) => None,
+        (Some(e), l) => Some(format!(
+            "見積 {} / 実績 {}",
+            format_minutes(e as i64),
+            format_minutes(l)
+        )),
+        (None, l) => Some(format!("見積 なし / 実績 {}", format_minutes(l))),
+    }
+}
+
+/// 見積もり工数を設定する
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `task_id` - タスクのID (UUIDv4)
+/// * `duration` - 見積もり（`1h30m`など）
+pub async fn estimate(
+    ctx: Context<'_>,
+    task_id: String,
+    duration: String,
+) -> Result<(), serenity::Error> {
+    let channel_id = ctx.channel_id().to_string();
+    let client = connect_to_db().await?;
+
+    let task_id = match find_task(&client, &channel_id, &task_id).await {
+        Some(id) => id,
+        None => {
+            reply(ctx, "このチャンネルにそのタスクはありません").await;
+            return Err(serenity::Error::Other("タスクが見つかりません"));
+        }
+    };
+    let Some(minutes) = parse_duration(&duration) else {
+        reply(ctx, "時間は 1h30m のような形式で入力してください").await;
+        return Err(serenity::Error::Other("時間の形式が不正です"));
+    };
+
+    let query = format!("update \"{}\" set estimate=$1 where id=$2;", channel_id);
+    match client.execute(&query, &[&minutes, &task_id]).await {
+        Ok(_) => {
+            reply(
+                ctx,
+                format!("見積もりを{}にしました", format_minutes(minutes as i64)),
+            )
+            .await
+        }
+        Err(_) => {
+            reply(ctx, "見積もりを設定できませんでした").await;
+            return Err(serenity::Error::Other("見積もりの設定に失敗しました"));
+        }
+    }
+    Ok(())
+}
+
+/// タイマーを開始する
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `task_id` - タスクのID (UUIDv4)
+pub async fn start(ctx: Context<'_>, task_id: String) -> Result<(), serenity::Error> {
+    let channel_id = ctx.channel_id().to_string();
+    let user_id = ctx.author().id.to_string();
+    let client = connect_to_db().await?;
+
+    let task_id = match find_task(&client, &channel_id, &task_id).await {
+        Some(id) => id,
+        None => {
+            reply(ctx, "このチャンネルにそのタスクはありません").await;
+            return Err(serenity::Error::Other("タスクが見つかりません"));
+        }
+    };
+
+    let res = client
+        .execute(
+            "insert into kipp.timers (user_id, task_id) values ($1, $2) on conflict do nothing;",
+            &[&user_id, &task_id],
+        )
+        .await;
+    match res {
+        Ok(0) => {
+            reply(
+                ctx,
+                "既にタイマーが動いています. /timer stop で止めてください",
+            )
+            .await;
+            return Err(serenity::Error::Other("タイマーが動いています"));
+        }
+        Ok(_) => reply(ctx, "\u{23f1} タイマーを開始しました").await,
+        Err(_) => {
+            reply(ctx, "タイマーを開始できませんでした").await;
+            return Err(serenity::Error::Other("タイマーの開始に失敗しました"));
+        }
+    }
+    Ok(())
+}
+
+/// 動いているタイマーを止めて作業時間を記録する
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+pub async fn stop(ctx: Context<'_>) -> Result<(), serenity::Error> {
+    let user_id = ctx.author().id.to_string();
+    let client = connect_to_db().await?;
+
+    // 1分未満は1分として記録する
+    let rows = client
+        .query(
+            "with t as (delete from kipp.timers where user_id=$1
+                returning task_id, user_id, started_at)
+            insert into kipp.time_logs (task_id, user_id, started_at, ended_at, minutes)
+            select task_id, user_id, started_at, now(),
+                greatest(1, round(extract(epoch from now() - started_at) / 60))::integer
+            from t
+            returning task_id, minutes;",
+            &[&user_id],
+        )
+        .await;
+    match rows {
+        Ok(rows) if rows.is_empty() => {
+            reply(ctx, "動いているタイマーはありません").await;
+            return Err(serenity::Error::Other("タイマーがありません"));
+        }
+        Ok(rows) => {
+            let task_id: uuid::Uuid = rows[0].get("task_id");
+            let minutes: i32 = rows[0].get("minutes");
+            reply(
+                ctx,
+                format!(
+                    "\u{23f9} #{} に{}を記録しました",
+                    short_id(&task_id),
+                    format_minutes(minutes as i64)
+                ),
+            )
+            .await;
+        }
+        Err(_) => {
+            reply(ctx, "タイマーを止められませんでした").await;
+            return Err(serenity::Error::Other("タイマーの停止に失敗しました"));
+        }
+    }
+    Ok(())
+}
+
+/// 作業時間を手入力で記録する
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `task_id` - タスクのID (UUIDv4)
+/// * `duration` - 作業時間（`1h30m`など）
+pub async fn log(
+    ctx: Context<'_>,
+    task_id: String,
+    duration: String,
+) -> Result<(), serenity::Error> {
+    let channel_id = ctx.channel_id().to_string();
+    let user_id = ctx.author().id.to_string();
+    let client = connect_to_db().await?;
+
+    let task_id = match find_task(&client, &channel_id, &task_id).await {
+        Some(id) => id,
+        None => {
+            reply(ctx, "このチャンネルにそのタスクはありません").await;
+            return Err(serenity::Error::Other("タスクが見つかりません"));
+        }
+    };
+    let Some(minutes) = parse_duration(&duration) else {
+        reply(ctx, "時間は 1h30m のような形式で入力してください").await;
+        return Err(serenity::Error::Other("時間の形式が不正です"));
+    };
+
+    let res = client
+        .execute(
+            "insert into kipp.time_logs (task_id, user_id, started_at, ended_at, minutes)
+            values ($1, $2, now() - make_interval(mins => $3), now(), $3);",
+            &[&task_id, &user_id, &minutes],
+        )
+        .await;
+    match res {
+        Ok(_) => {
+            reply(
+                ctx,
+                format!("{}を記録しました", format_minutes(minutes as i64)),
+            )
+            .await
+        }
+        Err(_) => {
+            reply(ctx, "作業時間を記録できませんでした").await;
+            return Err(serenity::Error::Other("作業時間の記録に失敗しました"));
+        }
+    }
+    Ok(())
+}
+
+/// 期間内にユーザーが記録した作業時間をタスクごとに集計する
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `user` - 集計するユーザー. 省略時は自分
+/// * `from` - 開始日 (YYYY-MM-DD). 省略時は今月1日
+/// * `to` - 終了日 (YYYY-MM-DD). 省略時は今日
+pub async fn report(
+    ctx: Context<'_>,
+    user: Option<serenity::User>,
+    from: Option<String>,
+    to: Option<String>,
+) -> Result<(), serenity::Error> {
+    let user = user.unwrap_or_else(|| ctx.author().clone());
+    let user_id = user.id.to_string();
+    let client = connect_to_db().await?;
+
+    // ---------- 期間 ----------
+    let today = Local::now().date_naive();
+    let parse = |d: Option<String>, default: NaiveDate| match d {
+        Some(d) => NaiveDate::parse_from_str(d.trim(), "%Y-%m-%d").ok(),
+        None => Some(default),
+    };
+    let (Some(from), Some(to)) = (
+        parse(from, today.with_day(1).unwrap_or(today)),
+        parse(to, today),
+    ) else {
+        reply(ctx, "日付は YYYY-MM-DD の形式で入力してください").await;
+        return Err(serenity::Error::Other("日付の形式が不正です"));
+    };
+
+    let rows = client
+        .query(
+            "select task_id, sum(minutes)::bigint as total from kipp.time_logs
+            where user_id=$1 and ended_at::date between $2 and $3
+            group by task_id order by total desc;",
+            &[&user_id, &from, &to],
+        )
+        .await
+        .unwrap_or_default();
+
+    let mut lines = String::new();
+    let mut total: i64 = 0;
+    for row in rows {
+        let task_id: uuid::Uuid = row.get("task_id");
+        let minutes: i64 = row.get("total");
+        total += minutes;
+        let name = match locate_task(&client, &task_id).await {
+            Some(ch) => task_summary(&client, &ch, &task_id)
+                .await
+                .map(|(name, _)| name),
+            None => None,
+        }
+        .unwrap_or_else(|| "削除されたタスク".to_string());
+        lines.push_str(&format!(
+            "`#{}` {} : {}\n",
+            short_id(&task_id),
+            name,
+            format_minutes(minutes)
+        ));
+    }
+    if lines.is_empty() {
+        lines.push_str("作業記録はありません");
+    }
+
+    let embed = CreateEmbed::default()
+        .title(format!(
+            "{} の作業時間 ({} 〜 {})",
+            user.name,
+            from.format("%Y-%m-%d"),
+            to.format("%Y-%m-%d")
+        ))
+        .description(lines)
+        .field(
+            "合計",
+            format!("{} ({:.2}h)", format_minutes(total), total as f64 / 60.0),
+            false,
+        );
+    let _ = ctx
+        .send(CreateReply::default().ephemeral(true).embed(embed))
+        .await;
+    Ok(())
+}
@@ -0,0 +1,424 @@
+//! タスクのチャンネル間の移動・コピーの実装
+//!
+//! 移動はタスクIDを変えずに行をテーブルごと移すので, タグやコメント,
+//! 作業記録などIDで紐付いているものはそのまま付いてくる.
+//! コピーは新しいIDでタスクを作り, タグとチェックリスト, マイルストーンだけを複製する.
+//! チャンネルごとにワークフローが違うことがあるので, ステータスは移動先の状態に付け替える.
+
+use crate::board;
+use crate::db::{connect_to_db, ensure_task_table, find_task, task_column_names, task_summary};
+use crate::support::{reply, short_id};
+use crate::workflow;
+use poise::serenity_prelude::{self as serenity, ChannelId, CreateMessage, GuildChannel, Http};
+use tokio_postgres::{Client, Transaction};
+
+pub type Context<'a> = poise::Context<'a, super::Data, serenity::Error>;
+
+/// チャンネルへお知らせを送る
+async fn notice(http: &Http, channel_id: ChannelId, content: String) {
+    let _ = channel_id
+        .send_message(http, CreateMessage::default().content(content))
+        .await;
+}
+
+/// 移動先のチャンネルが使えるか確認する
+async fn check_target(
+    ctx: Context<'_>,
+    client: &Client,
+    from: ChannelId,
+    to: &GuildChannel,
+) -> Result<(), serenity::Error> {
+    if from == to.id {
+        reply(ctx, "同じチャンネルは指定できません").await;
+        return Err(serenity::Error::Other("同じチャンネルです"));
+    }
+    if ensure_task_table(client, &to.id.to_string()).await.is_err() {
+        reply(ctx, "移動先のテーブルを用意できませんでした").await;
+        return Err(serenity::Error::Other("テーブルの作成に失敗しました"));
+    }
+    Ok(())
+}
+
+/// 移動先のワークフローに合わせたステータスの対応表
+///
+/// チャンネルごとにワークフローが違うことがあるので, 同じ名前の状態があればその番号にする.
+/// 無ければ完了扱いかどうかで 完了済み(0) か 最初の状態(1) にする.
+struct StatusMap {
+    /// 元のチャンネルでの番号
+    old: Vec<i16>,
+    /// 移動先のチャンネルでの番号
+    new: Vec<i16>,
+}
+
+impl StatusMap {
+    /// 元のテーブルにあるステータスの対応表を作る
+    async fn load(ctx: Context<'_>, client: &Client, from: ChannelId, to: ChannelId) -> Self {
+        let guild_id = ctx.guild_id().map(|id| id.to_string());
+        let from_states = workflow::states(client, &from.to_string(), guild_id.as_deref()).await;
+        let to_states = workflow::states(client, &to.to_string(), guild_id.as_deref()).await;
+        let old: Vec<i16> = client
+            .query(&format!("select distinct status from \"{}\";", from), &[])
+            .await
+            .map(|rows| rows.iter().map(|row| row.get("status")).collect())
+            .unwrap_or_default();
+        let new = old
+            .iter()
+            .map(|status| {
+                let name = workflow::name_of(&from_states, *status);
+                match to_states.iter().find(|s| s.name == name) {
+                    Some(state) => state.code,
+                    None if workflow::is_done(*status) => 0,
+                    None => 1,
+                }
+            })
+            .collect();
+        StatusMap { old, new }
+    }
+
+    /// 移動先のテーブルに移したタスクのステータスを付け替える
+    async fn apply(
+        &self,
+        tx: &Transaction<'_>,
+        to: ChannelId,
+        ids: &[uuid::Uuid],
+    ) -> Result<u64, tokio_postgres::Error> {
+        tx.execute(
+            &format!(
+                "update \"{}\" t set status = m.new
+                from unnest($1::smallint[], $2::smallint[]) as m(old, new)
+                where t.status = m.old and t.id = any($3);",
+                to
+            ),
+            &[&self.old, &self.new, &ids],
+        )
+        .await
+    }
+}
+
+/// タスクを別のチャンネルへ移動する
+///
+/// タスクIDは変わらない. ステータスは移動先のワークフローに合わせる.
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `task_id` - タスクのID (UUIDv4)
+/// * `channel` - 移動先のチャンネル
+pub async fn move_task(
+    ctx: Context<'_>,
+    task_id: String,
+    channel: GuildChannel,
+) -> Result<(), serenity::Error> {
+    let from = ctx.channel_id();
+    let mut client = connect_to_db().await?;
+
+    let task_id = match find_task(&client, &from.to_string(), &task_id).await {
+        Some(id) => id,
+        None => {
+            reply(ctx, "このチャンネルにそのタスクはありません").await;
+            return Err(serenity::Error::Other("タスクが見つかりません"));
+        }
+    };
+    check_target(ctx, &client, from, &channel).await?;
+    let (task_name, _) = task_summary(&client, &from.to_string(), &task_id)
+        .await
+        .unwrap_or_default();
+
+    // ---------- 行を移す ----------
+    let columns = task_column_names().join(", ");
+    let statuses = StatusMap::load(ctx, &client, from, channel.id).await;
+    let res: Result<(), tokio_postgres::Error> = async {
+        let tx = client.transaction().await?;
+        tx.execute(
+            &format!(
+                "insert into \"{}\" ({1}) select {1} from \"{2}\" where id=$1;",
+                channel.id, columns, from
+            ),
+            &[&task_id],
+        )
+        .await?;
+        statuses.apply(&tx, channel.id, &[task_id]).await?;
+        tx.execute(
+            &format!("delete from \"{}\" where id=$1;", from),
+            &[&task_id],
+        )
+        .await?;
+        // スプリントはチャンネルごとなので外す
+        tx.execute(
+            &format!("update \"{}\" set sprint_id=null where id=$1;", channel.id),
+            &[&task_id],
+        )
+        .await?;
+        tx.execute(
+            "update kipp.recurrences set channel_id=$2 where task_id=$1;",
+            &[&task_id, &channel.id.to_string()],
+        )
+        .await?;
+        tx.execute(
+            "update kipp.milestone_tasks set channel_id=$2 where task_id=$1;",
+            &[&task_id, &channel.id.to_string()],
+        )
+        .await?;
+        tx.commit().await
+    }
+    .await;
+    if res.is_err() {
+        reply(ctx, "タスクを移動できませんでした").await;
+        return Err(serenity::Error::Other("タスクの移動に失敗しました"));
+    }
+
+    // ---------- 0行だったらテーブルごと削除 ----------
+    let count_query = format!("select count(*) from \"{}\";", from);
+    if let Ok(rows) = client.query(&count_query, &[]).await {
+        if rows[0].get::<&str, i64>("count") == 0 {
+            let _ = client
+                .execute(&format!("drop table \"{}\";", from), &[])
+                .await;
+        }
+    }
+
+    // ---------- ボードを更新 ----------
+    let http = ctx.http();
+    board::refresh(http, &client, &from.to_string()).await;
+    board::refresh(http, &client, &channel.id.to_string()).await;
+
+    // ---------- お知らせ ----------
+    notice(
+        http,
+        from,
+        format!(
+            "\u{1f4e6} タスク「{}」(#{}) を <#{}> へ移動しました",
+            task_name,
+            short_id(&task_id),
+            channel.id
+        ),
+    )
+    .await;
+    notice(
+        http,
+        channel.id,
+        format!(
+            "\u{1f4e6} タスク「{}」(#{}) が <#{}> から移動してきました",
+            task_name,
+            short_id(&task_id),
+            from
+        ),
+    )
+    .await;
+    reply(ctx, "タスクを移動しました").await;
+    Ok(())
+}
+
+/// タスクを別のチャンネルへコピーする
+///
+/// コピーには新しいタスクIDが振られる. タグとチェックリスト, マイルストーンも複製する.
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `task_id` - タスクのID (UUIDv4)
+/// * `channel` - コピー先のチャンネル
+pub async fn copy_task(
+    ctx: Context<'_>,
+    task_id: String,
+    channel: GuildChannel,
+) -> Result<(), serenity::Error> {
+    let from = ctx.channel_id();
+    let mut client = connect_to_db().await?;
+
+    let task_id = match find_task(&client, &from.to_string(), &task_id).await {
+        Some(id) => id,
+        None => {
+            reply(ctx, "このチャンネルにそのタスクはありません").await;
+            return Err(serenity::Error::Other("タスクが見つかりません"));
+        }
+    };
+    check_target(ctx, &client, from, &channel).await?;
+    let (task_name, _) = task_summary(&client, &from.to_string(), &task_id)
+        .await
+        .unwrap_or_default();
+
+    // ---------- 新しいIDで複製 ----------
+    // スレッドは元のタスクのもの, スプリントは元のチャンネルのものなので引き継がない.
+    // 作成日時はコピーした日時にする
+    let columns: Vec<&str> = task_column_names()
+        .into_iter()
+        .filter(|c| !matches!(*c, "id" | "thread_id" | "sprint_id" | "created_at"))
+        .collect();
+    let columns = columns.join(", ");
+    let statuses = StatusMap::load(ctx, &client, from, channel.id).await;
+    let res: Result<uuid::Uuid, tokio_postgres::Error> = async {
+        let tx = client.transaction().await?;
+        let rows = tx
+            .query(
+                &format!(
+                    "insert into \"{}\" (id, {1}) select uuid_generate_v4(), {1} from \"{2}\"
+                    where id=$1 returning id;",
+                    channel.id, columns, from
+                ),
+                &[&task_id],
+            )
+            .await?;
+        let new_id: uuid::Uuid = rows[0].get("id");
+        statuses.apply(&tx, channel.id, &[new_id]).await?;
+        tx.execute(
+            "insert into kipp.task_tags (task_id, tag)
+            select $2, tag from kipp.task_tags where task_id=$1;",
+            &[&task_id, &new_id],
+        )
+        .await?;
+        tx.execute(
+            "insert into kipp.checklist_items (task_id, content, done)
+            select $2, content, done from kipp.checklist_items where task_id=$1 order by id;",
+            &[&task_id, &new_id],
+        )
+        .await?;
+        tx.execute(
+            "insert into kipp.checklists (task_id, auto_complete)
+            select $2, auto_complete from kipp.checklists where task_id=$1;",
+            &[&task_id, &new_id],
+        )
+        .await?;
+        tx.execute(
+            "insert into kipp.milestone_tasks (task_id, milestone_id, channel_id)
+            select $2, milestone_id, $3 from kipp.milestone_tasks where task_id=$1;",
+            &[&task_id, &new_id, &channel.id.to_string()],
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(new_id)
+    }
+    .await;
+    let new_id = match res {
+        Ok(id) => id,
+        Err(_) => {
+            reply(ctx, "タスクをコピーできませんでした").await;
+            return Err(serenity::Error::Other("タスクのコピーに失敗しました"));
+        }
+    };
+
+    // ---------- ボードを更新 ----------
+    let http = ctx.http();
+    board::refresh(http, &client, &from.to_string()).await;
+    board::refresh(http, &client, &channel.id.to_string()).await;
+
+    // ---------- お知らせ ----------
+    notice(
+        http,
+        from,
+        format!(
+            "\u{1f4cb} タスク「{}」(#{}) を <#{}> へコピーしました",
+            task_name,
+            short_id(&task_id),
+            channel.id
+        ),
+    )
+    .await;
+    notice(
+        http,
+        channel.id,
+        format!(
+            "\u{1f4cb} タスク「{}」を <#{}> からコピーしました (#{})",
+            task_name,
+            from,
+            short_id(&new_id)
+        ),
+    )
+    .await;
+    reply(ctx, format!("タスクをコピーしました\nタスクID: {}", new_id)).await;
+    Ok(())
+}
+
+/// このチャンネルのタスクをすべて別のチャンネルへ移動する
+///
+/// チャンネルの整理用. 空になったテーブルは削除する.
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `channel` - 移動先のチャンネル
+pub async fn move_all(ctx: Context<'_>, channel: GuildChannel) -> Result<(), serenity::Error> {
+    let from = ctx.channel_id();
+    let mut client = connect_to_db().await?;
+    check_target(ctx, &client, from, &channel).await?;
+
+    let columns = task_column_names().join(", ");
+    let statuses = StatusMap::load(ctx, &client, from, channel.id).await;
+    let res: Result<u64, tokio_postgres::Error> = async {
+        let tx = client.transaction().await?;
+        let ids: Vec<uuid::Uuid> = tx
+            .query(&format!("select id from \"{}\";", from), &[])
+            .await?
+            .iter()
+            .map(|row| row.get("id"))
+            .collect();
+        let count = tx
+            .execute(
+                &format!(
+                    "insert into \"{}\" ({1}) select {1} from \"{2}\";",
+                    channel.id, columns, from
+                ),
+                &[],
+            )
+            .await?;
+        statuses.apply(&tx, channel.id, &ids).await?;
+        tx.execute(
+            &format!(
+                "update \"{}\" set sprint_id=null where sprint_id in
+                (select id from kipp.sprints where channel_id=$1);",
+                channel.id
+            ),
+            &[&from.to_string()],
+        )
+        .await?;
+        tx.execute(&format!("drop table \"{}\";", from), &[])
+            .await?;
+        tx.execute(
+            "update kipp.recurrences set channel_id=$2 where channel_id=$1;",
+            &[&from.to_string(), &channel.id.to_string()],
+        )
+        .await?;
+        tx.execute(
+            "update kipp.milestone_tasks set channel_id=$2 where task_id = any($1);",
+            &[&ids, &channel.id.to_string()],
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(count)
+    }
+    .await;
+    let count = match res {
+        Ok(count) => count,
+        Err(_) => {
+            reply(ctx, "このチャンネルに移動できるタスクはありません").await;
+            return Err(serenity::Error::Other("タスクの移動に失敗しました"));
+        }
+    };
+
+    // ---------- ボードを更新 ----------
+    let http = ctx.http();
+    board::refresh(http, &client, &from.to_string()).await;
+    board::refresh(http, &client, &channel.id.to_string()).await;
+
+    // ---------- お知らせ ----------
+    notice(
+        http,
+        from,
+        format!(
+            "\u{1f4e6} タスク{}件を <#{}> へ移動しました",
+            count, channel.id
+        ),
+    )
+    .await;
+    notice(
+        http,
+        channel.id,
+        format!(
+            "\u{1f4e6} タスク{}件が <#{}> から移動してきました",
+            count, from
+        ),
+    )
+    .await;
+    reply(ctx, format!("タスクを{}件移動しました", count)).await;
+    Ok(())
+}
@@ -0,0 +1,238 @@
+//! 直前の操作を元に戻す実装
+//!
+//! `/status`と`/remove`は実行前にタスクの行を`kipp.undo`へ保存しておく.
+//! 保存するのはユーザーごとに最後の1件だけで, `UNDO_MINUTES`分を過ぎると戻せなくなる.
+//!
+//! 削除の場合はタグ・チェックリスト・コメント・マイルストーンも一緒に保存して戻す.
+//! 依存関係や繰り返しの設定は戻らない.
+
+use crate::board;
+use crate::db::{connect_to_db, ensure_task_table};
+use crate::imp;
+use crate::milestone;
+use crate::support::reply;
+use crate::workflow;
+use poise::serenity_prelude::{
+    self as serenity, ButtonStyle, ComponentInteraction, CreateActionRow, CreateButton,
+    CreateInteractionResponse, CreateInteractionResponseMessage, Http, Message,
+};
+use std::time::Duration;
+use tokio_postgres::Client;
+
+pub type Context<'a> = poise::Context<'a, super::Data, serenity::Error>;
+
+/// 元に戻せる時間（分）
+const UNDO_MINUTES: i32 = 5;
+
+/// 「元に戻す」ボタンを押せる時間（秒）
+///
+/// これを過ぎても`UNDO_MINUTES`分までは`/undo`で戻せる.
+const BUTTON_TIMEOUT: u64 = 60;
+
+/// 「元に戻す」ボタン
+pub fn button() -> CreateActionRow {
+    CreateActionRow::Buttons(vec![CreateButton::new("undo")
+        .label("元に戻す")
+        .style(ButtonStyle::Secondary)])
+}
+
+/// 操作の前にタスクの状態を保存する
+///
+/// # 引数
+///
+/// * `client` - DBクライアント
+/// * `user_id` - 操作したユーザー
+/// * `channel_id` - タスクのあるチャンネルID
+/// * `task_id` - タスクのID
+/// * `action` - `status`か`remove`
+pub async fn remember(
+    client: &Client,
+    user_id: &str,
+    channel_id: &str,
+    task_id: &uuid::Uuid,
+    action: &str,
+) {
+    let query = format!(
+        "insert into kipp.undo (user_id, channel_id, task_id, action, snapshot)
+        select $1, $2, t.id, $3, jsonb_build_object(
+            'row', to_jsonb(t),
+            'tags', coalesce((select jsonb_agg(tag) from kipp.task_tags
+                where task_id=t.id), '[]'),
+            'checklist', coalesce((select jsonb_agg(jsonb_build_object(
+                'content', content, 'done', done) order by id)
+                from kipp.checklist_items where task_id=t.id), '[]'),
+            'comments', coalesce((select jsonb_agg(jsonb_build_object(
+                'author', author, 'content', content, 'created_at', created_at) order by id)
+                from kipp.comments where task_id=t.id), '[]'),
+            'milestone', (select milestone_id from kipp.milestone_tasks where task_id=t.id))
+        from \"{}\" t where t.id=$4
+        on conflict (user_id) do update set channel_id = excluded.channel_id,
+            task_id = excluded.task_id, action = excluded.action,
+            snapshot = excluded.snapshot, created_at = now();",
+        channel_id
+    );
+    // タスクが無くて保存できなかったときに前の操作が残っていると, それを戻してしまう
+    match client
+        .execute(&query, &[&user_id, &channel_id, &action, task_id])
+        .await
+    {
+        Ok(1) => {}
+        _ => forget(client, user_id).await,
+    }
+}
+
+/// ユーザーの保存した操作を捨てる
+///
+/// 操作が失敗したときなど, 前の操作を戻されると困るときに呼ぶ.
+pub async fn forget(client: &Client, user_id: &str) {
+    let _ = client
+        .execute("delete from kipp.undo where user_id=$1;", &[&user_id])
+        .await;
+}
+
+/// ユーザーの直前の操作を元に戻す
+///
+/// 成功したらお知らせの文面を返す.
+async fn restore(http: &Http, client: &Client, user_id: &str) -> Result<String, &'static str> {
+    // 取り出したら消すので同じ操作を2回戻すことは無い
+    let rows = client
+        .query(
+            "delete from kipp.undo where user_id=$1
+            returning channel_id, task_id, action, snapshot,
+                now() - created_at < make_interval(mins => $2) as fresh;",
+            &[&user_id, &UNDO_MINUTES],
+        )
+        .await
+        .map_err(|_| "元に戻せませんでした")?;
+    let Some(row) = rows.first() else {
+        return Err("元に戻せる操作はありません");
+    };
+    if !row.get::<&str, bool>("fresh") {
+        return Err("元に戻せる時間を過ぎています");
+    }
+    let channel_id: String = row.get("channel_id");
+    let task_id: uuid::Uuid = row.get("task_id");
+    let action: String = row.get("action");
+    let snapshot: serde_json::Value = row.get("snapshot");
+    let task_name = snapshot["row"]["task_name"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+    let status = snapshot["row"]["status"].as_i64().unwrap_or(1) as i16;
+
+    match action.as_str() {
+        // ---------- ステータスを戻す ----------
+        "status" => {
+            let query = format!("update \"{}\" set status=$1 where id=$2;", channel_id);
+            match client.execute(&query, &[&status, &task_id]).await {
+                Ok(1) => {}
+                _ => return Err("タスクが見つからないので戻せませんでした"),
+            }
+            imp::after_status_change(http, client, &channel_id, &task_id, status).await;
+            let states = workflow::states_of(http, client, &channel_id).await;
+            Ok(format!(
+                "タスク「{}」のステータスを「{}」に戻しました",
+                task_name,
+                workflow::name_of(&states, status)
+            ))
+        }
+        // ---------- 削除したタスクを戻す ----------
+        "remove" => {
+            if ensure_task_table(client, &channel_id).await.is_err() {
+                return Err("テーブルを用意できませんでした");
+            }
+            let query = format!(
+                "insert into \"{0}\" select * from jsonb_populate_record(null::\"{0}\", $1);",
+                channel_id
+            );
+            if client.execute(&query, &[&snapshot["row"]]).await.is_err() {
+                return Err("タスクを戻せませんでした");
+            }
+            let _ = client
+                .execute(
+                    "insert into kipp.task_tags (task_id, tag)
+                    select $1, jsonb_array_elements_text($2) on conflict do nothing;",
+                    &[&task_id, &snapshot["tags"]],
+                )
+                .await;
+            let _ = client
+                .execute(
+                    "insert into kipp.checklist_items (task_id, content, done)
+                    select $1, x->>'content', (x->>'done')::boolean
+                    from jsonb_array_elements($2) x;",
+                    &[&task_id, &snapshot["checklist"]],
+                )
+                .await;
+            let _ = client
+                .execute(
+                    "insert into kipp.comments (task_id, author, content, created_at)
+                    select $1, x->>'author', x->>'content', (x->>'created_at')::timestamptz
+                    from jsonb_array_elements($2) x;",
+                    &[&task_id, &snapshot["comments"]],
+                )
+                .await;
+            if let Some(milestone_id) = snapshot["milestone"].as_i64() {
+                let _ = milestone::attach(client, milestone_id as i32, &task_id, &channel_id).await;
+            }
+            board::refresh(http, client, &channel_id).await;
+            Ok(format!("削除したタスク「{}」を戻しました", task_name))
+        }
+        _ => Err("元に戻せませんでした"),
+    }
+}
+
+/// メッセージの「元に戻す」ボタンが押されるのを待って元に戻す
+///
+/// 押せるのはコマンドを実行した本人だけ. 押されずに時間切れになったら`false`を返すので,
+/// ボタンを消すのは呼び出し側でやる.
+pub async fn offer(ctx: Context<'_>, msg: &Message) -> bool {
+    let Some(mi) = msg
+        .await_component_interaction(ctx)
+        .author_id(ctx.author().id)
+        .custom_ids(vec!["undo".to_string()])
+        .timeout(Duration::from_secs(BUTTON_TIMEOUT))
+        .await
+    else {
+        return false;
+    };
+    respond(ctx, &mi).await;
+    true
+}
+
+/// ボタンの操作に元に戻した結果で応答する
+async fn respond(ctx: Context<'_>, mi: &ComponentInteraction) {
+    let content = match connect_to_db().await {
+        Ok(client) => match restore(ctx.http(), &client, &ctx.author().id.to_string()).await {
+            Ok(msg) => msg,
+            Err(msg) => msg.to_string(),
+        },
+        Err(_) => "元に戻せませんでした".to_string(),
+    };
+    let _ = mi
+        .create_response(
+            ctx,
+            CreateInteractionResponse::UpdateMessage(
+                CreateInteractionResponseMessage::new()
+                    .content(content)
+                    .components(vec![]),
+            ),
+        )
+        .await;
+}
+
+/// 直前の操作を元に戻す
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+pub async fn undo(ctx: Context<'_>) -> Result<(), serenity::Error> {
+    let client = connect_to_db().await?;
+    match restore(ctx.http(), &client, &ctx.author().id.to_string()).await {
+        Ok(msg) => reply(ctx, msg).await,
+        Err(msg) => {
+            reply(ctx, msg).await;
+            return Err(serenity::Error::Other("元に戻せませんでした"));
+        }
+    }
+    Ok(())
+}
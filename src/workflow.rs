@@ -0,0 +1,662 @@
+//! ステータスのワークフローの実装
+//!
+//! チャンネルかギルドごとに, ステータスの並び・色・完了扱いかどうかを決められる.
+//! チャンネルの設定, ギルドの設定, 既定の3つ（進行中/未着手/完了済み）の順に探す.
+//!
+//! タスクテーブルの`status`列には状態ごとに振った番号を入れる.
+//! - 最初の状態（新しいタスクの状態）= 1
+//! - 最初の完了扱いの状態 = 0
+//! - それ以外の未完了の状態 = 2以上, 完了扱いの状態 = -1以下
+//!
+//! なので **0以下なら完了扱い** になる. 既定のワークフローの番号もこれに合っている.
+//!
+//! 遷移ルールを決めると, ある状態から移れる状態を制限したり, 移るときにコメントを
+//! 必須にしたりできる. ルールの無い状態からはどこへでも移れる.
+
+use crate::db::connect_to_db;
+use crate::imp::status_name;
+use crate::support::reply;
+use crate::tag::{parse_color, parse_tags};
+use poise::serenity_prelude::{self as serenity, ChannelId, CreateEmbed, Http};
+use poise::CreateReply;
+use tokio_postgres::Client;
+
+pub type Context<'a> = poise::Context<'a, super::Data, serenity::Error>;
+
+/// セレクトメニューに載せられる上限（Discordの仕様）
+const STATE_LIMIT: usize = 25;
+
+/// ワークフローの状態
+pub struct State {
+    /// `status`列に入る番号
+    pub code: i16,
+    /// 表示名
+    pub name: String,
+    /// 埋め込みの色
+    pub color: Option<i32>,
+    /// 完了扱いかどうか
+    pub done: bool,
+}
+
+/// ワークフローを設定する範囲
+#[derive(poise::ChoiceParameter, PartialEq)]
+pub enum Scope {
+    #[name = "このチャンネル"]
+    Channel,
+    #[name = "サーバー全体"]
+    Guild,
+}
+
+/// 完了扱いのステータスかどうか
+pub fn is_done(status: i16) -> bool {
+    status <= 0
+}
+
+/// 既定のワークフロー
+///
+/// NOTE: ステータス
+/// - 進行中 = 2
+/// - 未着手 = 1
+/// - 完了済み = 0
+pub fn default_states() -> Vec<State> {
+    [(2, Some(0x00ff00)), (1, None), (0, None)]
+        .into_iter()
+        .map(|(code, color)| State {
+            code,
+            name: status_name(code).to_string(),
+            color,
+            done: is_done(code),
+        })
+        .collect()
+}
+
+/// 設定範囲(チャンネルIDかギルドID)のワークフローを取得
+///
+/// 設定が無ければ空を返す.
+async fn load(client: &Client, scope_id: &str) -> Vec<State> {
+    client
+        .query(
+            "select code, name, color, done from kipp.workflow_states
+            where scope_id=$1 order by position;",
+            &[&scope_id],
+        )
+        .await
+        .unwrap_or_default()
+        .iter()
+        .map(|row| State {
+            code: row.get("code"),
+            name: row.get("name"),
+            color: row.get("color"),
+            done: row.get("done"),
+        })
+        .collect()
+}
+
+/// チャンネルで使うワークフローを取得
+///
+/// チャンネル, ギルド, 既定の順に探す.
+pub async fn states(client: &Client, channel_id: &str, guild_id: Option<&str>) -> Vec<State> {
+    let states = load(client, channel_id).await;
+    if !states.is_empty() {
+        return states;
+    }
+    if let Some(guild_id) = guild_id {
+        let states = load(client, guild_id).await;
+        if !states.is_empty() {
+            return states;
+        }
+    }
+    default_states()
+}
+
+/// コマンドの外（ボタンやイベント）からワークフローを取得
+///
+/// ギルドIDはチャンネルから調べる.
+pub async fn states_of(http: &Http, client: &Client, channel_id: &str) -> Vec<State> {
+    let guild_id = match channel_id.parse::<u64>() {
+        Ok(id) => ChannelId::new(id)
+            .to_channel(http)
+            .await
+            .ok()
+            .and_then(|ch| ch.guild())
+            .map(|ch| ch.guild_id.to_string()),
+        Err(_) => None,
+    };
+    states(client, channel_id, guild_id.as_deref()).await
+}
+
+/// ステータスの表示名
+///
+/// ワークフローに無い番号は既定の名前にする.
+pub fn name_of(states: &[State], status: i16) -> String {
+    match states.iter().find(|s| s.code == status) {
+        Some(state) => state.name.clone(),
+        None => status_name(status).to_string(),
+    }
+}
+
+/// 状態の遷移
+pub struct Transition {
+    /// 移れる先の状態の番号
+    pub to: i16,
+    /// 移るときにコメントが必要か
+    pub require_comment: bool,
+}
+
+/// ある状態から移れる状態を取得
+///
+/// 遷移ルールはワークフローと同じくチャンネル, ギルドの順に探す.
+/// その状態にルールが無ければ制限なしとして`None`を返す.
+pub async fn transitions(
+    client: &Client,
+    channel_id: &str,
+    guild_id: Option<&str>,
+    from: i16,
+) -> Option<Vec<Transition>> {
+    let scopes: Vec<&str> = [Some(channel_id), guild_id].into_iter().flatten().collect();
+    let rows = client
+        .query(
+            "select scope_id, to_code, require_comment from kipp.workflow_transitions
+            where scope_id = any($1) and from_code=$2;",
+            &[&scopes, &from],
+        )
+        .await
+        .ok()?;
+    // チャンネルのルールがあればギルドのルールは見ない
+    let scope = scopes.iter().find(|s| {
+        rows.iter()
+            .any(|row| row.get::<&str, &str>("scope_id") == **s)
+    })?;
+    Some(
+        rows.iter()
+            .filter(|row| row.get::<&str, &str>("scope_id") == *scope)
+            .map(|row| Transition {
+                to: row.get("to_code"),
+                require_comment: row.get("require_comment"),
+            })
+            .collect(),
+    )
+}
+
+/// 状態の変更が許されているか確かめる
+///
+/// 許されていれば`Ok(コメントが必要か)`, 許されていなければ断りの文面を返す.
+pub fn check_transition(
+    states: &[State],
+    rules: &Option<Vec<Transition>>,
+    from: i16,
+    to: i16,
+) -> Result<bool, String> {
+    let Some(rules) = rules else {
+        return Ok(false);
+    };
+    match rules.iter().find(|t| t.to == to) {
+        Some(t) => Ok(t.require_comment),
+        None => {
+            let allowed: Vec<String> = rules.iter().map(|t| name_of(states, t.to)).collect();
+            Err(format!(
+                "「{}」から「{}」へは変更できません（変更できるのは: {}）",
+                name_of(states, from),
+                name_of(states, to),
+                if allowed.is_empty() {
+                    "なし".to_string()
+                } else {
+                    allowed.join(", ")
+                }
+            ))
+        }
+    }
+}
+
+/// 状態名のオートコンプリート
+pub async fn autocomplete_state(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let Ok(client) = connect_to_db().await else {
+        return Vec::new();
+    };
+    let guild_id = ctx.guild_id().map(|id| id.to_string());
+    states(&client, &ctx.channel_id().to_string(), guild_id.as_deref())
+        .await
+        .into_iter()
+        .map(|s| s.name)
+        .filter(|name| name.starts_with(partial.trim()))
+        .collect()
+}
+
+/// `名前 #RRGGBB`のカンマ区切りを(名前, 色)に分解する
+fn parse_states(input: &str) -> Option<Vec<(String, Option<i32>)>> {
+    let mut res: Vec<(String, Option<i32>)> = Vec::new();
+    for item in input.split(',') {
+        let item = item.trim();
+        if item.is_empty() {
+            continue;
+        }
+        let (name, color) = match item.rsplit_once('#') {
+            Some((name, color)) => (name.trim(), Some(parse_color(color)?)),
+            None => (item, None),
+        };
+        if name.is_empty() || res.iter().any(|(n, _)| n == name) {
+            return None;
+        }
+        res.push((name.to_string(), color));
+    }
+    Some(res)
+}
+
+/// 新しいワークフローに番号を振る
+///
+/// 以前の設定に同じ名前で同じ完了扱いの状態があれば番号を引き継ぐので,
+/// 並べ替えや追加をしても既存のタスクの状態は変わらない.
+fn assign_codes(parsed: Vec<(String, Option<i32>, bool)>, old: &[State]) -> Vec<State> {
+    let mut used: Vec<i16> = Vec::new();
+    let mut codes: Vec<Option<i16>> = vec![None; parsed.len()];
+
+    // ---------- 最初の状態と最初の完了扱いの状態 ----------
+    codes[0] = Some(1);
+    used.push(1);
+    if let Some(i) = parsed.iter().position(|(_, _, done)| *done) {
+        codes[i] = Some(0);
+        used.push(0);
+    }
+
+    // ---------- 以前の番号を引き継ぐ ----------
+    for (i, (name, _, done)) in parsed.iter().enumerate() {
+        if codes[i].is_some() {
+            continue;
+        }
+        let prev = old
+            .iter()
+            .find(|s| s.name == *name && s.done == *done)
+            .map(|s| s.code)
+            .filter(|code| !used.contains(code) && *code != 0 && *code != 1);
+        if let Some(code) = prev {
+            codes[i] = Some(code);
+            used.push(code);
+        }
+    }
+
+    // ---------- 残りは空いている番号 ----------
+    for (i, (_, _, done)) in parsed.iter().enumerate() {
+        if codes[i].is_some() {
+            continue;
+        }
+        let mut code: i16 = if *done { -1 } else { 2 };
+        while used.contains(&code) {
+            code += if *done { -1 } else { 1 };
+        }
+        codes[i] = Some(code);
+        used.push(code);
+    }
+
+    parsed
+        .into_iter()
+        .zip(codes)
+        .map(|((name, color, done), code)| State {
+            code: code.unwrap_or(1),
+            name,
+            color,
+            done,
+        })
+        .collect()
+}
+
+/// 設定範囲のIDを決める
+fn scope_id(ctx: Context<'_>, scope: &Option<Scope>) -> String {
+    if scope == &Some(Scope::Guild) {
+        ctx.guild_id().unwrap().to_string()
+    } else {
+        ctx.channel_id().to_string()
+    }
+}
+
+/// ワークフローを設定する
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `states` - 状態の並び. `名前 #RRGGBB`をカンマ区切りで. 最初の状態が新しいタスクの状態になる
+/// * `done` - 完了扱いにする状態名（カンマ区切り）. 省略時は最後の状態
+/// * `scope` - 設定範囲. 省略時はこのチャンネル
+pub async fn set(
+    ctx: Context<'_>,
+    states: String,
+    done: Option<String>,
+    scope: Option<Scope>,
+) -> Result<(), serenity::Error> {
+    let scope_id = scope_id(ctx, &scope);
+    let guild_id = ctx.guild_id().unwrap().to_string();
+    let mut client = connect_to_db().await?;
+
+    // ---------- 入力の解釈 ----------
+    let Some(parsed) = parse_states(&states) else {
+        reply(
+            ctx,
+            "状態は「名前 #RRGGBB」をカンマ区切りで入力してください（名前の重複は不可）",
+        )
+        .await;
+        return Err(serenity::Error::Other("ワークフローの形式が不正です"));
+    };
+    if parsed.len() < 2 || STATE_LIMIT < parsed.len() {
+        reply(ctx, format!("状態は2〜{}個にしてください", STATE_LIMIT)).await;
+        return Err(serenity::Error::Other("状態の数が不正です"));
+    }
+    let done_names: Vec<String> = match &done {
+        Some(done) => parse_tags(done),
+        None => vec![parsed[parsed.len() - 1].0.clone()],
+    };
+    if done_names
+        .iter()
+        .any(|d| !parsed.iter().any(|(name, _)| name == d))
+    {
+        reply(ctx, "完了扱いの状態は states の中から選んでください").await;
+        return Err(serenity::Error::Other("完了扱いの状態が不正です"));
+    }
+    if done_names.contains(&parsed[0].0) {
+        reply(ctx, "最初の状態は完了扱いにできません").await;
+        return Err(serenity::Error::Other("最初の状態が完了扱いです"));
+    }
+    let parsed: Vec<(String, Option<i32>, bool)> = parsed
+        .into_iter()
+        .map(|(name, color)| {
+            let done = done_names.contains(&name);
+            (name, color, done)
+        })
+        .collect();
+
+    // NOTE: 初めて設定する範囲でも, 今使われている状態の番号を引き継ぐ
+    let old = if scope_id == guild_id {
+        self::states(&client, &guild_id, None).await
+    } else {
+        self::states(&client, &scope_id, Some(&guild_id)).await
+    };
+    let new_states = assign_codes(parsed, &old);
+    // 意味の変わらない番号. これ以外の番号を使う遷移ルールは消す
+    let kept: Vec<i16> = new_states
+        .iter()
+        .filter(|s| {
+            old.iter()
+                .any(|o| o.code == s.code && o.name == s.name && o.done == s.done)
+        })
+        .map(|s| s.code)
+        .collect();
+
+    // ---------- まとめて保存 ----------
+    let res: Result<(), tokio_postgres::Error> = async {
+        let tx = client.transaction().await?;
+        tx.execute(
+            "delete from kipp.workflow_states where scope_id=$1;",
+            &[&scope_id],
+        )
+        .await?;
+        for (i, state) in new_states.iter().enumerate() {
+            tx.execute(
+                "insert into kipp.workflow_states
+                (scope_id, guild_id, position, code, name, color, done)
+                values ($1, $2, $3, $4, $5, $6, $7);",
+                &[
+                    &scope_id,
+                    &guild_id,
+                    &(i as i16),
+                    &state.code,
+                    &state.name,
+                    &state.color,
+                    &state.done,
+                ],
+            )
+            .await?;
+        }
+        tx.execute(
+            "delete from kipp.workflow_transitions
+            where scope_id=$1 and not (from_code = any($2) and to_code = any($2));",
+            &[&scope_id, &kept],
+        )
+        .await?;
+        tx.commit().await
+    }
+    .await;
+
+    match res {
+        Ok(_) => {
+            let names: Vec<&str> = new_states.iter().map(|s| s.name.as_str()).collect();
+            reply(
+                ctx,
+                format!("ワークフローを設定しました: {}", names.join(" → ")),
+            )
+            .await
+        }
+        Err(_) => {
+            reply(ctx, "ワークフローを設定できませんでした").await;
+            return Err(serenity::Error::Other("ワークフローの設定に失敗しました"));
+        }
+    }
+    Ok(())
+}
+
+/// このチャンネルで使われるワークフローを表示
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+pub async fn show(ctx: Context<'_>) -> Result<(), serenity::Error> {
+    let client = connect_to_db().await?;
+    let guild_id = ctx.guild_id().map(|id| id.to_string());
+    let states = states(&client, &ctx.channel_id().to_string(), guild_id.as_deref()).await;
+
+    let channel_id = ctx.channel_id().to_string();
+    let mut lines = String::new();
+    for state in &states {
+        lines.push_str(&format!("`{}`", state.name));
+        if let Some(c) = state.color {
+            lines.push_str(&format!(" #{:06x}", c));
+        }
+        if state.done {
+            lines.push_str(" （完了扱い）");
+        }
+        // ---------- 遷移ルール ----------
+        if let Some(rules) =
+            transitions(&client, &channel_id, guild_id.as_deref(), state.code).await
+        {
+            let next: Vec<String> = rules
+                .iter()
+                .map(|t| {
+                    let name = name_of(&states, t.to);
+                    if t.require_comment {
+                        format!("{}（要コメント）", name)
+                    } else {
+                        name
+                    }
+                })
+                .collect();
+            lines.push_str(&format!(" → {}", next.join(", ")));
+        }
+        lines.push('\n');
+    }
+
+    let embed = CreateEmbed::default()
+        .title("ワークフロー")
+        .description(lines);
+    let _ = ctx
+        .send(CreateReply::default().ephemeral(true).embed(embed))
+        .await;
+    Ok(())
+}
+
+/// ワークフローの設定を消して既定に戻す
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `scope` - 設定範囲. 省略時はこのチャンネル
+pub async fn reset(ctx: Context<'_>, scope: Option<Scope>) -> Result<(), serenity::Error> {
+    let scope_id = scope_id(ctx, &scope);
+    let client = connect_to_db().await?;
+
+    let res = client
+        .execute(
+            "delete from kipp.workflow_states where scope_id=$1;",
+            &[&scope_id],
+        )
+        .await;
+    // 番号が変わるので遷移ルールも消す
+    let _ = client
+        .execute(
+            "delete from kipp.workflow_transitions where scope_id=$1;",
+            &[&scope_id],
+        )
+        .await;
+    match res {
+        Ok(0) => reply(ctx, "ワークフローは設定されていません").await,
+        Ok(_) => reply(ctx, "ワークフローの設定を消しました").await,
+        Err(_) => {
+            reply(ctx, "ワークフローの設定を消せませんでした").await;
+            return Err(serenity::Error::Other("ワークフローの削除に失敗しました"));
+        }
+    }
+    Ok(())
+}
+
+/// 状態名から番号を探す
+fn code_of(states: &[State], name: &str) -> Option<i16> {
+    states
+        .iter()
+        .find(|s| s.name == name.trim())
+        .map(|s| s.code)
+}
+
+/// 遷移ルールを追加する
+///
+/// ある状態にルールを1つでも追加すると, その状態からはルールにある状態にしか移れなくなる.
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `from` - 元の状態名
+/// * `to` - 移れる先の状態名（カンマ区切り）
+/// * `require_comment` - 移るときにコメントを必須にするか
+/// * `scope` - 設定範囲. 省略時はこのチャンネル
+pub async fn allow(
+    ctx: Context<'_>,
+    from: String,
+    to: String,
+    require_comment: Option<bool>,
+    scope: Option<Scope>,
+) -> Result<(), serenity::Error> {
+    let scope_id = scope_id(ctx, &scope);
+    let client = connect_to_db().await?;
+    let guild_id = ctx.guild_id().map(|id| id.to_string());
+    let states = states(&client, &ctx.channel_id().to_string(), guild_id.as_deref()).await;
+    let require_comment = require_comment.unwrap_or(false);
+
+    // ---------- 状態名の確認 ----------
+    let Some(from_code) = code_of(&states, &from) else {
+        reply(ctx, "元の状態がワークフローにありません").await;
+        return Err(serenity::Error::Other("状態が見つかりません"));
+    };
+    let to_codes: Option<Vec<i16>> = parse_tags(&to)
+        .iter()
+        .map(|name| code_of(&states, name))
+        .collect();
+    let to_codes = match to_codes {
+        Some(codes) if !codes.is_empty() && !codes.contains(&from_code) => codes,
+        _ => {
+            reply(
+                ctx,
+                "移れる先の状態はワークフローの中から, 元の状態以外を選んでください",
+            )
+            .await;
+            return Err(serenity::Error::Other("状態が見つかりません"));
+        }
+    };
+
+    for code in &to_codes {
+        let res = client
+            .execute(
+                "insert into kipp.workflow_transitions
+                (scope_id, from_code, to_code, require_comment) values ($1, $2, $3, $4)
+                on conflict (scope_id, from_code, to_code)
+                do update set require_comment = excluded.require_comment;",
+                &[&scope_id, &from_code, code, &require_comment],
+            )
+            .await;
+        if res.is_err() {
+            reply(ctx, "遷移ルールを追加できませんでした").await;
+            return Err(serenity::Error::Other("遷移ルールの追加に失敗しました"));
+        }
+    }
+
+    let names: Vec<String> = to_codes.iter().map(|c| name_of(&states, *c)).collect();
+    reply(
+        ctx,
+        format!(
+            "「{}」から「{}」へ移れるようにしました{}",
+            name_of(&states, from_code),
+            names.join(", "),
+            if require_comment {
+                "（要コメント）"
+            } else {
+                ""
+            }
+        ),
+    )
+    .await;
+    Ok(())
+}
+
+/// 遷移ルールを削除する
+///
+/// 移れる先を省略するとその状態のルールをすべて消し, どこへでも移れるように戻す.
+///
+/// # 引数
+///
+/// * `ctx` - コマンド起動時の情報が入ったブツ
+/// * `from` - 元の状態名
+/// * `to` - 消すルールの移れる先の状態名（カンマ区切り, 任意）
+/// * `scope` - 設定範囲. 省略時はこのチャンネル
+pub async fn disallow(
+    ctx: Context<'_>,
+    from: String,
+    to: Option<String>,
+    scope: Option<Scope>,
+) -> Result<(), serenity::Error> {
+    let scope_id = scope_id(ctx, &scope);
+    let client = connect_to_db().await?;
+    let guild_id = ctx.guild_id().map(|id| id.to_string());
+    let states = states(&client, &ctx.channel_id().to_string(), guild_id.as_deref()).await;
+
+    let Some(from_code) = code_of(&states, &from) else {
+        reply(ctx, "元の状態がワークフローにありません").await;
+        return Err(serenity::Error::Other("状態が見つかりません"));
+    };
+    let to_codes: Vec<i16> = match &to {
+        Some(to) => parse_tags(to)
+            .iter()
+            .filter_map(|name| code_of(&states, name))
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let res = if to.is_none() {
+        client
+            .execute(
+                "delete from kipp.workflow_transitions where scope_id=$1 and from_code=$2;",
+                &[&scope_id, &from_code],
+            )
+            .await
+    } else {
+        client
+            .execute(
+                "delete from kipp.workflow_transitions
+                where scope_id=$1 and from_code=$2 and to_code = any($3);",
+                &[&scope_id, &from_code, &to_codes],
+            )
+            .await
+    };
+    match res {
+        Ok(0) => reply(ctx, "該当する遷移ルールはありません").await,
+        Ok(count) => reply(ctx, format!("遷移ルールを{}件削除しました", count)).await,
+        Err(_) => {
+            reply(ctx, "遷移ルールを削除できませんでした").await;
+            return Err(serenity::Error::Other("遷移ルールの削除に失敗しました"));
+        }
+    }
+    Ok(())
+}